@@ -0,0 +1,13 @@
+// something in the input that was transcribed, but probably not as intended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    // 1-based
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
@@ -0,0 +1,124 @@
+// the emoji for `:shortcode:`, without the colons
+pub fn emoji(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by_key(&shortcode, |(shortcode, _)| shortcode)
+        .ok()
+        .map(|ix| EMOJI[ix].1)
+}
+
+// whether `c` may appear in a shortcode
+pub fn is_shortcode_char(c: u8) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, b'_' | b'+' | b'-')
+}
+
+// NOTE: sorted by shortcode, for `emoji`'s binary search
+pub const EMOJI: [(&str, &str); 108] = [
+    ("+1", "\u{1f44d}"),
+    ("-1", "\u{1f44e}"),
+    ("100", "\u{1f4af}"),
+    ("airplane", "\u{2708}\u{fe0f}"),
+    ("alarm_clock", "\u{23f0}"),
+    ("angry", "\u{1f620}"),
+    ("arrow_down", "\u{2b07}\u{fe0f}"),
+    ("arrow_left", "\u{2b05}\u{fe0f}"),
+    ("arrow_right", "\u{27a1}\u{fe0f}"),
+    ("arrow_up", "\u{2b06}\u{fe0f}"),
+    ("balloon", "\u{1f388}"),
+    ("bell", "\u{1f514}"),
+    ("blush", "\u{1f60a}"),
+    ("bomb", "\u{1f4a3}"),
+    ("books", "\u{1f4da}"),
+    ("boom", "\u{1f4a5}"),
+    ("broken_heart", "\u{1f494}"),
+    ("bug", "\u{1f41b}"),
+    ("bulb", "\u{1f4a1}"),
+    ("calendar", "\u{1f4c6}"),
+    ("cat", "\u{1f431}"),
+    ("chart_with_downwards_trend", "\u{1f4c9}"),
+    ("chart_with_upwards_trend", "\u{1f4c8}"),
+    ("clap", "\u{1f44f}"),
+    ("clipboard", "\u{1f4cb}"),
+    ("cloud", "\u{2601}\u{fe0f}"),
+    ("coffee", "\u{2615}"),
+    ("confused", "\u{1f615}"),
+    ("construction", "\u{1f6a7}"),
+    ("cool", "\u{1f192}"),
+    ("cry", "\u{1f622}"),
+    ("dog", "\u{1f436}"),
+    ("exclamation", "\u{2757}"),
+    ("eyes", "\u{1f440}"),
+    ("facepalm", "\u{1f926}"),
+    ("fire", "\u{1f525}"),
+    ("gear", "\u{2699}\u{fe0f}"),
+    ("ghost", "\u{1f47b}"),
+    ("gift", "\u{1f381}"),
+    ("grey_question", "\u{2754}"),
+    ("grimacing", "\u{1f62c}"),
+    ("grin", "\u{1f601}"),
+    ("grinning", "\u{1f600}"),
+    ("hammer", "\u{1f528}"),
+    ("hammer_and_wrench", "\u{1f6e0}\u{fe0f}"),
+    ("heart", "\u{2764}\u{fe0f}"),
+    ("heart_eyes", "\u{1f60d}"),
+    ("heavy_check_mark", "\u{2714}\u{fe0f}"),
+    ("heavy_minus_sign", "\u{2796}"),
+    ("heavy_plus_sign", "\u{2795}"),
+    ("hourglass", "\u{231b}"),
+    ("hugs", "\u{1f917}"),
+    ("information_source", "\u{2139}\u{fe0f}"),
+    ("joy", "\u{1f602}"),
+    ("key", "\u{1f511}"),
+    ("laughing", "\u{1f606}"),
+    ("link", "\u{1f517}"),
+    ("lock", "\u{1f512}"),
+    ("mag", "\u{1f50d}"),
+    ("memo", "\u{1f4dd}"),
+    ("moon", "\u{1f319}"),
+    ("muscle", "\u{1f4aa}"),
+    ("no_entry", "\u{26d4}"),
+    ("ok", "\u{1f197}"),
+    ("ok_hand", "\u{1f44c}"),
+    ("package", "\u{1f4e6}"),
+    ("partying_face", "\u{1f973}"),
+    ("pencil2", "\u{270f}\u{fe0f}"),
+    ("point_down", "\u{1f447}"),
+    ("point_left", "\u{1f448}"),
+    ("point_right", "\u{1f449}"),
+    ("point_up", "\u{261d}\u{fe0f}"),
+    ("pray", "\u{1f64f}"),
+    ("pushpin", "\u{1f4cc}"),
+    ("question", "\u{2753}"),
+    ("raised_hands", "\u{1f64c}"),
+    ("recycle", "\u{267b}\u{fe0f}"),
+    ("rocket", "\u{1f680}"),
+    ("rotating_light", "\u{1f6a8}"),
+    ("scream", "\u{1f631}"),
+    ("see_no_evil", "\u{1f648}"),
+    ("shrug", "\u{1f937}"),
+    ("skull", "\u{1f480}"),
+    ("sleeping", "\u{1f634}"),
+    ("slightly_smiling_face", "\u{1f642}"),
+    ("smile", "\u{1f604}"),
+    ("smiley", "\u{1f603}"),
+    ("smirk", "\u{1f60f}"),
+    ("sob", "\u{1f62d}"),
+    ("sparkles", "\u{2728}"),
+    ("star", "\u{2b50}"),
+    ("stop_sign", "\u{1f6d1}"),
+    ("sunglasses", "\u{1f60e}"),
+    ("sunny", "\u{2600}\u{fe0f}"),
+    ("sweat_smile", "\u{1f605}"),
+    ("tada", "\u{1f389}"),
+    ("thinking", "\u{1f914}"),
+    ("thumbsdown", "\u{1f44e}"),
+    ("thumbsup", "\u{1f44d}"),
+    ("trophy", "\u{1f3c6}"),
+    ("unlock", "\u{1f513}"),
+    ("warning", "\u{26a0}\u{fe0f}"),
+    ("wave", "\u{1f44b}"),
+    ("white_check_mark", "\u{2705}"),
+    ("wink", "\u{1f609}"),
+    ("wrench", "\u{1f527}"),
+    ("x", "\u{274c}"),
+    ("zap", "\u{26a1}"),
+];
@@ -1,14 +1,92 @@
 use std::io::{self, Write};
 
+pub mod diagnostic;
+pub mod emoji;
+pub mod math;
+pub mod metadata;
+pub mod options;
+pub mod refs;
+pub mod tasks;
+pub mod toc;
 pub mod transcriber;
+pub use diagnostic::Diagnostic;
+pub use metadata::Metadata;
+pub use options::{DisallowedLinks, LinkRewriter, Options, OutputMode};
+pub use tasks::Task;
+pub use toc::Heading;
 pub use transcriber::Transcriber;
 
 pub fn transcribe<O: Write>(input: &[u8], output: &mut O) -> SamupResult {
-    let mut transcriber = Transcriber::new();
+    transcribe_with(input, output, &Options::default())
+}
+
+pub fn transcribe_with<O: Write>(input: &[u8], output: &mut O, options: &Options) -> SamupResult {
+    transcribe_with_diagnostics(input, output, options)?;
+    Ok(())
+}
+
+// transcribes `input`, returning anything that looked off along the way
+pub fn transcribe_with_diagnostics<O: Write>(
+    input: &[u8],
+    output: &mut O,
+    options: &Options,
+) -> SamupResult<Vec<Diagnostic>> {
+    // link definitions may come after the links that use them
+    let mut transcriber =
+        Transcriber::with_options(options.clone()).with_definitions(refs::definitions(input));
+    // the table of contents needs every heading up front, so take a first pass for them
+    if options.toc || toc::has_toc_directive(input) {
+        transcriber = transcriber.with_toc(headings_with(input, options)?);
+    }
     while transcriber.ix < input.len() {
         transcriber.transcribe(input, output)?;
     }
-    transcriber.finish(output)
+    transcriber.finish(output)?;
+    Ok(transcriber.into_diagnostics())
+}
+
+pub fn diagnostics_with(input: &[u8], options: &Options) -> SamupResult<Vec<Diagnostic>> {
+    transcribe_with_diagnostics(input, &mut io::sink(), options)
+}
+
+// the front matter at the start of `input`, if any
+pub fn metadata(input: &[u8]) -> Metadata {
+    metadata::front_matter(input)
+        .map(|(metadata, _)| metadata)
+        .unwrap_or_default()
+}
+
+pub fn headings_with(input: &[u8], options: &Options) -> SamupResult<Vec<Heading>> {
+    Ok(transcribe_to_sink(input, options)?.into_headings())
+}
+
+// every `- [ ]` and `- [x]` task in `input`, in order
+pub fn tasks_with(input: &[u8], options: &Options) -> SamupResult<Vec<Task>> {
+    Ok(transcribe_to_sink(input, options)?.into_tasks())
+}
+
+// the tasks in `input` not done yet
+pub fn open_tasks_with(input: &[u8], options: &Options) -> SamupResult<Vec<Task>> {
+    let mut tasks = tasks_with(input, options)?;
+    tasks.retain(|task| !task.done);
+    Ok(tasks)
+}
+
+pub fn toc_with<O: Write>(input: &[u8], output: &mut O, options: &Options) -> SamupResult {
+    let headings = headings_with(input, options)?;
+    toc::write_toc(&headings, output, options.output_mode, 0)
+}
+
+// a transcriber that's been through all of `input`, for what it collected along the way
+fn transcribe_to_sink(input: &[u8], options: &Options) -> SamupResult<Transcriber> {
+    let mut transcriber =
+        Transcriber::with_options(options.clone()).with_definitions(refs::definitions(input));
+    let mut output = io::sink();
+    while transcriber.ix < input.len() {
+        transcriber.transcribe(input, &mut output)?;
+    }
+    transcriber.finish(&mut output)?;
+    Ok(transcriber)
 }
 
 pub type SamupResult<T = ()> = Result<T, io::Error>;
@@ -20,14 +98,30 @@ pub enum C {
     Underscore,
     Asterisk,
     Octothorpe,
-    // NOTE: only in FootNoteLink or FootNoteRef
+    // [^1] or ^sup^
     Caret,
-    // NOTE: only in FootNoteRef
+    // NOTE: only in FootNoteRef, or around an emoji :shortcode:
     Colon,
     SqBracketL,
     SqBracketR,
     ParenL,
     ParenR,
+    // NOTE: only at the start of a line
+    Gt,
+    // NOTE: only in tables
+    Pipe,
+    // NOTE: only before images
+    Bang,
+    // ~sub~ or ~~del~~
+    Tilde,
+    // NOTE: only in ++u++
+    Plus,
+    // NOTE: only in comments, at the start of a line
+    Percent,
+    // NOTE: only before a newline, for a hard line break, or before `$`
+    Backslash,
+    // $math$ or $$math$$
+    Dollar,
     // Quote,
     Digit, // for footnotes
     Content,
@@ -46,9 +140,13 @@ impl From<u8> for C {
             42 => C::Asterisk,
             // #
             35 => C::Octothorpe,
-            // ^ (NOTE: only in FootNoteLink or FootNoteRef)
+            // ^ ([^1] or ^sup^)
             94 => C::Caret,
-            // : (NOTE: only in FootNoteRef)
+            // ~
+            126 => C::Tilde,
+            // +
+            43 => C::Plus,
+            // : (NOTE: only in FootNoteRef, or around an emoji :shortcode:)
             58 => C::Colon,
             // [
             91 => C::SqBracketL,
@@ -58,6 +156,18 @@ impl From<u8> for C {
             40 => C::ParenL,
             // )
             41 => C::ParenR,
+            // > (NOTE: only at the start of a line)
+            62 => C::Gt,
+            // | (NOTE: only in tables)
+            124 => C::Pipe,
+            // ! (NOTE: only before images)
+            33 => C::Bang,
+            // % (NOTE: only in comments, at the start of a line)
+            37 => C::Percent,
+            // \ (NOTE: only before a newline, for a hard line break, or before `$`)
+            92 => C::Backslash,
+            // $
+            36 => C::Dollar,
             // "
             // 34 => C::Quote,
             // 0..=9
@@ -68,18 +178,24 @@ impl From<u8> for C {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct FootNoteIx(u8);
+pub struct FootNoteIx(u32);
 
 impl FootNoteIx {
     fn new(c: u8) -> Self {
-        Self(char_to_digit(c))
+        Self(char_to_digit(c).into())
     }
-    fn push_digit(&mut self, c: u8) {
-        let c: u8 = char_to_digit(c);
-        self.0 *= 10;
-        self.0 += c;
+    // false (leaving the index as is) if the digit would overflow it
+    fn push_digit(&mut self, c: u8) -> bool {
+        let c = u32::from(char_to_digit(c));
+        match self.0.checked_mul(10).and_then(|n| n.checked_add(c)) {
+            Some(n) => {
+                self.0 = n;
+                true
+            }
+            None => false,
+        }
     }
-    fn ix(&self) -> u8 {
+    fn ix(&self) -> u32 {
         self.0
     }
 }
@@ -102,29 +218,75 @@ impl HLevel {
             false
         }
     }
-    fn as_octothorpes(&self) -> &[u8] {
-        match self.0 {
-            1 => b"#",
-            2 => b"##",
-            3 => b"###",
-            4 => b"####",
-            5 => b"#####",
-            6 => b"######",
-            _ => panic!("unreachable HLevel"),
-        }
-    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum LinkState {
     Link,
     Label,
+    // a label whose url isn't allowed, written without the link
+    Disallowed,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InnerLink {
     state: LinkState,
     url: String,
+    // the url as written, shown for `[url]` links even once rewritten
+    label: String,
+    // {title="..." rel=... target=... download}
+    attrs: Vec<(String, String)>,
+}
+
+impl InnerLink {
+    fn new(state: LinkState, url: String) -> Self {
+        Self {
+            state,
+            label: url.clone(),
+            url,
+            attrs: Vec::new(),
+        }
+    }
+    fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    fn write_open<O: Write>(&self, output: &mut O) -> Result<(), io::Error> {
+        write!(output, "<a href=\"{}\"", escape_attr(&self.url))?;
+        // `{target=_self}` (or just `{target=}`) opens the link in the same tab
+        let target = self.attr("target").unwrap_or("_blank").trim();
+        if !target.is_empty() {
+            write!(output, " target=\"{}\"", escape_attr(target))?;
+        }
+        // keep the new tab from reaching back through `window.opener`, whatever `rel` adds
+        let mut rel = Vec::new();
+        // browsers match the keyword whatever its case
+        if target.eq_ignore_ascii_case("_blank") {
+            rel.extend(["noopener", "noreferrer"]);
+        }
+        for token in self
+            .attr("rel")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+        {
+            if !rel.iter().any(|t| t.eq_ignore_ascii_case(token)) {
+                rel.push(token);
+            }
+        }
+        if !rel.is_empty() {
+            write!(output, " rel=\"{}\"", escape_attr(&rel.join(" ")))?;
+        }
+        for (key, value) in &self.attrs {
+            match (key.as_str(), value.as_str()) {
+                ("target" | "rel", _) => (),
+                (key, "") => write!(output, " {key}")?,
+                (key, value) => write!(output, " {key}=\"{}\"", escape_attr(value))?,
+            }
+        }
+        output.write_all(b">")
+    }
 }
 
 impl std::fmt::Display for InnerLink {
@@ -133,13 +295,81 @@ impl std::fmt::Display for InnerLink {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InnerImage {
+    src: String,
+    alt: String,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    // |---|
+    #[default]
+    None,
+    // |:--|
+    Left,
+    // |:-:|
+    Center,
+    // |--:|
+    Right,
+}
+
+impl Align {
+    fn style(&self) -> Option<&'static str> {
+        match self {
+            Align::None => None,
+            Align::Left => Some("left"),
+            Align::Center => Some("center"),
+            Align::Right => Some("right"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InnerTable {
+    aligns: Vec<Align>,
+    // whether <tbody> has been opened
+    body: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Tag {
-    H(HLevel),
+    // level, id
+    H(HLevel, String),
     I,
     P,
+    // > ...
+    Blockquote,
+    // term\n: definition
+    Dl,
+    Dt,
+    Dd,
+    // - [ ] task\n- [x] done
+    Ul,
+    // whether it's done
+    Task(bool),
+    // !!! type\n...\n!!!
+    Callout(String),
+    // +++ summary\n...\n+++
+    Details,
+    // ---
+    Hr,
+    // | a | b |
+    Table(InnerTable),
     Strong,
+    // ~~...~~
+    Del,
+    // ++...++
+    U,
+    // ^...^
+    Sup,
+    // ~...~
+    Sub,
     Link(InnerLink),
+    // ![src](alt)
+    Image(InnerImage),
     // ...[^1]
     FootNoteLink(FootNoteIx),
     // [^1]: ...
@@ -149,16 +379,33 @@ pub enum Tag {
 impl std::fmt::Display for Tag {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
-            Tag::H(n) => {
+            Tag::H(n, id) => {
                 let level = n.level();
-                f.write_fmt(format_args!("<h{level}>"))
+                f.write_fmt(format_args!("<h{level} #{id}>"))
             }
             Tag::I => f.write_str("<i>"),
             Tag::P => f.write_str("<p>"),
+            Tag::Blockquote => f.write_str("<blockquote>"),
+            Tag::Dl => f.write_str("<dl>"),
+            Tag::Dt => f.write_str("<dt>"),
+            Tag::Dd => f.write_str("<dd>"),
+            Tag::Ul => f.write_str("<ul>"),
+            Tag::Task(done) => f.write_fmt(format_args!("<task: {done}>")),
+            Tag::Callout(kind) => f.write_fmt(format_args!("<callout: {kind}>")),
+            Tag::Details => f.write_str("<details>"),
+            Tag::Hr => f.write_str("<hr>"),
+            Tag::Table(InnerTable { aligns, .. }) => {
+                f.write_fmt(format_args!("<table: {aligns:?}>"))
+            }
             Tag::Strong => f.write_str("<strong>"),
-            Tag::Link(InnerLink { state, url }) => {
+            Tag::Del => f.write_str("<del>"),
+            Tag::U => f.write_str("<u>"),
+            Tag::Sup => f.write_str("<sup>"),
+            Tag::Sub => f.write_str("<sub>"),
+            Tag::Link(InnerLink { state, url, .. }) => {
                 f.write_fmt(format_args!("<link: {url} {state:?}>"))
             }
+            Tag::Image(InnerImage { src, .. }) => f.write_fmt(format_args!("<image: {src}>")),
             Tag::FootNoteLink(n) => {
                 let ix = n.ix();
                 f.write_fmt(format_args!("<footnote link {ix}>"))
@@ -174,15 +421,52 @@ impl std::fmt::Display for Tag {
 impl Tag {
     fn write_open<O: Write>(&self, output: &mut O) -> Result<(), io::Error> {
         match self {
-            Tag::H(n) => {
+            Tag::H(n, id) => {
                 let level = n.level();
-                output.write_fmt(format_args!("<h{level}>"))
+                write!(output, "<h{level} id=\"{id}\">")
             }
             Tag::I => output.write_all(b"<i>"),
             Tag::P => output.write_all(b"<p>"),
+            Tag::Blockquote => output.write_all(b"<blockquote>"),
+            Tag::Dl => output.write_all(b"<dl>"),
+            Tag::Dt => output.write_all(b"<dt>"),
+            Tag::Dd => output.write_all(b"<dd>"),
+            Tag::Ul => output.write_all(b"<ul>"),
+            Tag::Task(false) => output.write_all(b"<li><input type=\"checkbox\" disabled> "),
+            Tag::Task(true) => output.write_all(b"<li><input type=\"checkbox\" checked disabled> "),
+            Tag::Callout(kind) => write!(output, "<aside class=\"callout {}\">", escape_attr(kind)),
+            Tag::Details => output.write_all(b"<details>"),
+            Tag::Hr => output.write_all(b"<hr>"),
+            Tag::Table(_) => output.write_all(b"<table>"),
             Tag::Strong => output.write_all(b"<strong>"),
-            Tag::Link(InnerLink { url, .. }) => {
-                write!(output, "<a href=\"{url}\" target=\"_blank\">")
+            Tag::Del => output.write_all(b"<del>"),
+            Tag::U => output.write_all(b"<u>"),
+            Tag::Sup => output.write_all(b"<sup>"),
+            Tag::Sub => output.write_all(b"<sub>"),
+            Tag::Link(InnerLink {
+                state: LinkState::Disallowed,
+                ..
+            }) => Ok(()),
+            Tag::Link(link) => link.write_open(output),
+            Tag::Image(InnerImage {
+                src,
+                alt,
+                width,
+                height,
+            }) => {
+                write!(
+                    output,
+                    "<img src=\"{}\" alt=\"{}\"",
+                    escape_attr(src),
+                    escape_attr(alt)
+                )?;
+                if let Some(width) = width {
+                    write!(output, " width=\"{width}\"")?;
+                }
+                if let Some(height) = height {
+                    write!(output, " height=\"{height}\"")?;
+                }
+                output.write_all(b">")
             }
             Tag::FootNoteLink(_) => Ok(()),
             Tag::FootNoteRef(note_no) => {
@@ -196,23 +480,48 @@ impl Tag {
     }
     fn write_close<O: Write>(&self, output: &mut O) -> Result<(), io::Error> {
         match self {
-            Tag::H(n) => {
+            Tag::H(n, _) => {
                 let level = n.level();
                 output.write_fmt(format_args!("</h{level}>"))
             }
             Tag::I => output.write_all(b"</i>"),
             Tag::P => output.write_all(b"</p>"),
+            Tag::Blockquote => output.write_all(b"</blockquote>"),
+            Tag::Dl => output.write_all(b"</dl>"),
+            Tag::Dt => output.write_all(b"</dt>"),
+            Tag::Dd => output.write_all(b"</dd>"),
+            Tag::Ul => output.write_all(b"</ul>"),
+            Tag::Task(_) => output.write_all(b"</li>"),
+            Tag::Callout(_) => output.write_all(b"</aside>"),
+            Tag::Details => output.write_all(b"</details>"),
+            // void
+            Tag::Hr => Ok(()),
+            Tag::Table(_) => output.write_all(b"</table>"),
             Tag::Strong => output.write_all(b"</strong>"),
-            Tag::Link(InnerLink {
-                url,
-                state: LinkState::Link,
-            }) => {
-                write!(output, "<a href=\"{url}\" target=\"_blank\">{url}</a>")
+            Tag::Del => output.write_all(b"</del>"),
+            Tag::U => output.write_all(b"</u>"),
+            Tag::Sup => output.write_all(b"</sup>"),
+            Tag::Sub => output.write_all(b"</sub>"),
+            Tag::Link(
+                link @ InnerLink {
+                    label,
+                    state: LinkState::Link,
+                    ..
+                },
+            ) => {
+                link.write_open(output)?;
+                write!(output, "{}</a>", escape_attr(label))
             }
             Tag::Link(InnerLink {
                 state: LinkState::Label,
                 ..
             }) => output.write_all(b"</a>"),
+            Tag::Link(InnerLink {
+                state: LinkState::Disallowed,
+                ..
+            }) => Ok(()),
+            // void
+            Tag::Image(_) => Ok(()),
             Tag::FootNoteLink(note_no) => {
                 let note_no = note_no.ix();
                 write!(
@@ -226,13 +535,6 @@ impl Tag {
             }
         }
     }
-    fn new_link(c: u8) -> Self {
-        let c: &[u8] = &[c];
-        Tag::Link(InnerLink {
-            url: (unsafe { str::from_utf8_unchecked(c) }).into(),
-            state: LinkState::Link,
-        })
-    }
     fn write_link_no_title<O: Write>(&self, output: &mut O) -> Result<(), io::Error> {
         if let tag @ Tag::Link(InnerLink {
             state: LinkState::Link,
@@ -244,53 +546,30 @@ impl Tag {
             panic!()
         }
     }
-    fn push_link(&mut self, s: &str) {
-        if let Tag::Link(InnerLink {
-            url,
-            state: LinkState::Link,
-        }) = self
-        {
-            url.push_str(s);
-        } else {
-            panic!()
-        }
-    }
-    fn end_url(&mut self) {
-        if let Tag::Link(inner) = self
-            && let InnerLink { state, .. } = inner
-            && *state == LinkState::Link
-        {
-            *state = LinkState::Label;
-        } else {
-            panic!()
-        }
-    }
-    fn link_url(&self) -> &str {
-        if let Tag::Link(InnerLink { url, .. }) = self {
-            url
-        } else {
-            panic!()
-        }
-    }
-    fn new_h() -> Self {
-        Tag::H(HLevel::new())
-    }
-    fn inc_h(&mut self) -> bool {
-        if let Tag::H(n) = self {
-            n.inc_level()
-        } else {
-            panic!()
-        }
+    fn is_block(&self) -> bool {
+        self.is_container()
+            || matches!(
+                self,
+                Tag::H(..)
+                    | Tag::P
+                    | Tag::Dt
+                    | Tag::Dd
+                    | Tag::Task(_)
+                    | Tag::Hr
+                    | Tag::Table(_)
+                    | Tag::FootNoteRef(_)
+            )
     }
-    fn new_fn_link(c: u8) -> Self {
-        Tag::FootNoteLink(FootNoteIx::new(c))
+    // blocks written across several lines in pretty output
+    fn is_multiline(&self) -> bool {
+        self.is_container() || matches!(self, Tag::Table(_))
     }
-    fn push_fn_digit(&mut self, c: u8) {
-        if let Tag::FootNoteLink(n) | Tag::FootNoteRef(n) = self {
-            n.push_digit(c);
-        } else {
-            panic!()
-        }
+    // blocks holding other blocks rather than inline content
+    fn is_container(&self) -> bool {
+        matches!(
+            self,
+            Tag::Blockquote | Tag::Dl | Tag::Ul | Tag::Callout(_) | Tag::Details
+        )
     }
 }
 
@@ -301,3 +580,91 @@ pub fn char_to_digit(c: u8) -> u8 {
         .try_into()
         .expect("bad digit")
 }
+
+// lowercased words joined by `-`, for use as an html id
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+// the scheme of `url`, e.g. `https` for `https://...`, if it has one
+pub fn url_scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+// `&`, `"`, `<` and `>` as entities, for use in an attribute value
+pub fn escape_attr(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// `text` with `&`, `<` and `>` as entities
+pub fn write_escaped<O: Write>(output: &mut O, text: &[u8]) -> SamupResult {
+    let mut start = 0;
+    for (ix, c) in text.iter().enumerate() {
+        let entity: &[u8] = match c {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            _ => continue,
+        };
+        output.write_all(&text[start..ix])?;
+        output.write_all(entity)?;
+        start = ix + 1;
+    }
+    output.write_all(&text[start..])
+}
+
+// undoes `escape_attr`
+pub fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+// `line` without any leading whitespace and `>` quote markers
+pub fn unquote(line: &[u8]) -> &[u8] {
+    let markers = line
+        .iter()
+        .take_while(|c| c.is_ascii_whitespace() || **c == b'>');
+    &line[markers.count()..]
+}
+
+// the text content of transcribed html
+pub fn strip_tags(html: &[u8]) -> String {
+    let mut text = Vec::new();
+    let mut in_tag = false;
+    for c in html {
+        match c {
+            b'<' => in_tag = true,
+            b'>' => in_tag = false,
+            _ if !in_tag => text.push(*c),
+            _ => (),
+        }
+    }
+    String::from_utf8_lossy(&text).into_owned()
+}
@@ -1,5 +1,83 @@
-#[allow(unused_imports)]
 use samup::*;
-fn main() {
-    println!("Hello, world!");
+use std::io::{self, BufWriter, Read, Write};
+
+const USAGE: &str =
+    "usage: samup [--minified] [--anchors] [--no-autolink] [--no-math] [--emoji] [--html-links[=BASE]] [--keep-comments] [--raw-html | --safe] [--callouts=TYPES] [--toc | --toc-only] [FILE]
+
+transcribes FILE (or stdin) to html on stdout, and any problems to stderr
+
+  --minified  no whitespace between blocks
+  --anchors   append a \u{b6} link to every heading
+  --no-autolink
+              leave bare urls as plain text
+  --no-math   leave $...$ and $$...$$ as plain text instead of mathml
+  --emoji     replace :shortcode:s like :tada: with their emoji
+  --html-links[=BASE]
+              point links to other .samup files at their .html, under BASE, a
+              directory whether or not it ends in / (links starting with /
+              go under its origin instead)
+  --keep-comments
+              write %% comments as <!-- --> comments
+  --raw-html  write ~~~html ... ~~~ blocks as is
+  --safe      never write raw html, for untrusted input
+  --callouts=TYPES
+              comma-separated !!! callout types, instead of note,warning,danger
+  --toc       insert a table of contents at the top
+  --toc-only  only print the table of contents
+";
+
+fn main() -> SamupResult {
+    let mut options = Options::new();
+    let mut toc_only = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--minified" => options = options.output_mode(OutputMode::Minified),
+            "--anchors" => options = options.heading_anchors(true),
+            "--no-autolink" => options = options.autolink(false),
+            "--no-math" => options = options.math(false),
+            "--emoji" => options = options.emoji(true),
+            "--keep-comments" => options = options.keep_comments(true),
+            "--raw-html" => options = options.raw_html(true),
+            "--safe" => options = options.safe(true),
+            "--html-links" => options = options.samup_links(""),
+            _ if arg.starts_with("--html-links=") => {
+                options = options.samup_links(&arg["--html-links=".len()..]);
+            }
+            _ if arg.starts_with("--callouts=") => {
+                options = options.callouts(arg["--callouts=".len()..].split(','));
+            }
+            "--toc" => options = options.toc(true),
+            "--toc-only" => toc_only = true,
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with('-') || path.is_some() => {
+                eprint!("{USAGE}");
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unexpected argument {arg}"),
+                ));
+            }
+            _ => path = Some(arg),
+        }
+    }
+    let input = match path {
+        Some(path) => std::fs::read(path)?,
+        None => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input)?;
+            input
+        }
+    };
+    let mut output = BufWriter::new(io::stdout().lock());
+    if toc_only {
+        toc_with(&input, &mut output, &options)?;
+    } else {
+        for diagnostic in transcribe_with_diagnostics(&input, &mut output, &options)? {
+            eprintln!("samup: {diagnostic}");
+        }
+    }
+    output.flush()
 }
@@ -0,0 +1,470 @@
+use crate::escape_attr;
+
+// a TeX subset as mathml: numbers, letters and operators, `{...}` groups, `_` and `^`,
+// `\frac`, `\sqrt`, `\text`, accents, greek letters, common operators and functions,
+// `\left`/`\right` and spacing; anything else is an error
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        ix: 0,
+        display,
+        depth: 0,
+    };
+    let row = parser.row(false)?;
+    let open = if display {
+        "<math display=\"block\">"
+    } else {
+        "<math>"
+    };
+    Ok(format!("{open}{}</math>", row.concat()))
+}
+
+// the most groups and arguments open at once, well short of overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+struct Parser {
+    chars: Vec<char>,
+    ix: usize,
+    display: bool,
+    // groups and arguments open around the current character
+    depth: usize,
+}
+
+// a parsed element, and whether its scripts go under and over it in display math
+struct Node {
+    mathml: String,
+    limits: bool,
+}
+
+impl Node {
+    fn new(mathml: String) -> Self {
+        Self {
+            mathml,
+            limits: false,
+        }
+    }
+}
+
+impl Parser {
+    // elements up to the end of the input, or the `}` ending a group
+    fn row(&mut self, group: bool) -> Result<Vec<String>, String> {
+        let mut row = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if group => return Err(String::from("unclosed { in math")),
+                None => return Ok(row),
+                Some('}') if group => {
+                    self.ix += 1;
+                    return Ok(row);
+                }
+                Some('}') => return Err(String::from("unmatched } in math")),
+                Some('_' | '^') => {
+                    // a script with nothing before it
+                    let base = Node::new(String::from("<mrow></mrow>"));
+                    row.push(self.scripts(base)?);
+                }
+                Some(_) => {
+                    if let Some(node) = self.atom(false)? {
+                        row.push(self.scripts(node)?);
+                    }
+                }
+            }
+        }
+    }
+    // `base` with any `_` and `^` after it
+    fn scripts(&mut self, base: Node) -> Result<String, String> {
+        let (mut sub, mut sup) = (None, None);
+        loop {
+            self.skip_whitespace();
+            let script = match self.peek() {
+                Some('_') => &mut sub,
+                Some('^') => &mut sup,
+                _ => break,
+            };
+            if script.is_some() {
+                return Err(String::from("double subscript or superscript in math"));
+            }
+            self.ix += 1;
+            *script = Some(self.argument()?);
+        }
+        let limits = base.limits && self.display;
+        let base = base.mathml;
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) if limits => format!("<munder>{base}{sub}</munder>"),
+            (Some(sub), None) => format!("<msub>{base}{sub}</msub>"),
+            (None, Some(sup)) if limits => format!("<mover>{base}{sup}</mover>"),
+            (None, Some(sup)) => format!("<msup>{base}{sup}</msup>"),
+            (Some(sub), Some(sup)) if limits => {
+                format!("<munderover>{base}{sub}{sup}</munderover>")
+            }
+            (Some(sub), Some(sup)) => format!("<msubsup>{base}{sub}{sup}</msubsup>"),
+        })
+    }
+    // a `{...}` group or a single element, e.g. each half of `\frac12`
+    fn argument(&mut self) -> Result<String, String> {
+        self.nested(|parser| {
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => Err(String::from("missing argument in math")),
+                Some('{') => {
+                    parser.ix += 1;
+                    Ok(mrow(parser.row(true)?))
+                }
+                Some(_) => match parser.atom(true)? {
+                    Some(node) => Ok(node.mathml),
+                    None => Ok(String::from("<mrow></mrow>")),
+                },
+            }
+        })
+    }
+    // the element starting at the current character; `single` takes one digit of a number
+    fn atom(&mut self, single: bool) -> Result<Option<Node>, String> {
+        self.nested(|parser| {
+            let Some(c) = parser.next() else {
+                return Ok(None);
+            };
+            let node = match c {
+                '{' => Node::new(mrow(parser.row(true)?)),
+                '}' => return Err(String::from("unmatched } in math")),
+                '\\' => return parser.command(),
+                c if c.is_ascii_digit() => {
+                    let mut number = String::from(c);
+                    while !single
+                        && let Some(c) = parser.peek()
+                        && (c.is_ascii_digit()
+                            || c == '.' && parser.peek_at(1).is_some_and(|c| c.is_ascii_digit()))
+                    {
+                        number.push(c);
+                        parser.ix += 1;
+                    }
+                    Node::new(format!("<mn>{number}</mn>"))
+                }
+                c if c.is_alphabetic() => Node::new(format!("<mi>{c}</mi>")),
+                '-' => mo("\u{2212}"),
+                '*' => mo("\u{2217}"),
+                '\'' => mo("\u{2032}"),
+                '~' => mspace("0.278em"),
+                c => mo(&c.to_string()),
+            };
+            Ok(Some(node))
+        })
+    }
+    // `\name` or `\c`, just after the `\`
+    fn command(&mut self) -> Result<Option<Node>, String> {
+        let start = self.ix;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.ix += 1;
+        }
+        if self.ix == start {
+            return match self.next() {
+                None => Err(String::from("trailing \\ in math")),
+                Some(',') => Ok(Some(mspace("0.167em"))),
+                Some(':' | '>') => Ok(Some(mspace("0.222em"))),
+                Some(';' | ' ') => Ok(Some(mspace("0.278em"))),
+                Some('!') => Ok(Some(mspace("-0.167em"))),
+                Some('\\') => Ok(Some(Node::new(String::from(
+                    "<mspace linebreak=\"newline\"></mspace>",
+                )))),
+                Some(c) => Ok(Some(mo(&c.to_string()))),
+            };
+        }
+        let name: String = self.chars[start..self.ix].iter().collect();
+        if let Some(c) = greek(&name) {
+            let variant = if c.is_uppercase() {
+                " mathvariant=\"normal\""
+            } else {
+                ""
+            };
+            return Ok(Some(Node::new(format!("<mi{variant}>{c}</mi>"))));
+        }
+        if let Some(c) = symbol(&name) {
+            return Ok(Some(Node::new(format!("<mi>{c}</mi>"))));
+        }
+        if let Some(c) = operator(&name) {
+            return Ok(Some(mo(c)));
+        }
+        if let Some(c) = large_operator(&name) {
+            return Ok(Some(Node {
+                // integrals keep their limits to the side
+                limits: !matches!(name.as_str(), "int" | "iint" | "oint"),
+                ..mo(c)
+            }));
+        }
+        if FUNCTIONS.contains(&name.as_str()) {
+            return Ok(Some(Node {
+                mathml: format!("<mi>{name}</mi>"),
+                limits: LIMIT_FUNCTIONS.contains(&name.as_str()),
+            }));
+        }
+        let node = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                Node::new(format!("<mfrac>{numerator}{denominator}</mfrac>"))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.ix += 1;
+                    let mut index = Parser {
+                        chars: self.until(']')?.chars().collect(),
+                        ix: 0,
+                        display: false,
+                        depth: self.depth,
+                    };
+                    let index = mrow(index.row(false)?);
+                    let radicand = self.argument()?;
+                    Node::new(format!("<mroot>{radicand}{index}</mroot>"))
+                } else {
+                    Node::new(format!("<msqrt>{}</msqrt>", self.argument()?))
+                }
+            }
+            "text" | "textrm" | "mbox" => Node::new(format!("<mtext>{}</mtext>", self.text()?)),
+            "mathrm" | "mathbf" | "mathit" | "mathbb" | "mathcal" => {
+                let variant = match name.as_str() {
+                    "mathrm" => "normal",
+                    "mathbf" => "bold",
+                    "mathit" => "italic",
+                    "mathbb" => "double-struck",
+                    _ => "script",
+                };
+                Node::new(format!(
+                    "<mi mathvariant=\"{variant}\">{}</mi>",
+                    self.text()?
+                ))
+            }
+            "bar" | "overline" | "hat" | "widehat" | "tilde" | "widetilde" | "vec" | "dot"
+            | "ddot" => {
+                let accent = match name.as_str() {
+                    "bar" | "overline" => "\u{af}",
+                    "hat" | "widehat" => "^",
+                    "tilde" | "widetilde" => "~",
+                    "vec" => "\u{2192}",
+                    "dot" => "\u{2d9}",
+                    _ => "\u{a8}",
+                };
+                let base = self.argument()?;
+                Node::new(format!(
+                    "<mover accent=\"true\">{base}<mo>{accent}</mo></mover>"
+                ))
+            }
+            "left" | "right" => {
+                self.skip_whitespace();
+                // `\left.` is an invisible delimiter
+                if self.peek() == Some('.') {
+                    self.ix += 1;
+                    return Ok(None);
+                }
+                return self.atom(true);
+            }
+            "quad" => mspace("1em"),
+            "qquad" => mspace("2em"),
+            _ => return Err(format!("unknown math command \\{name}")),
+        };
+        Ok(Some(node))
+    }
+    // the raw contents of a `{...}` argument, escaped
+    fn text(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.next() != Some('{') {
+            return Err(String::from("missing { in math"));
+        }
+        Ok(escape_attr(&self.until('}')?))
+    }
+    // the characters up to `end`, which is consumed
+    fn until(&mut self, end: char) -> Result<String, String> {
+        let start = self.ix;
+        while let Some(c) = self.next() {
+            if c == end {
+                return Ok(self.chars[start..self.ix - 1].iter().collect());
+            }
+        }
+        Err(format!("missing {end} in math"))
+    }
+    // `parse` one level deeper, failing rather than overflowing the stack on deep nesting
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(String::from("math nested too deeply"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.ix += 1;
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.ix + offset).copied()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.ix += 1;
+        Some(c)
+    }
+}
+
+// `row` as a single element
+fn mrow(row: Vec<String>) -> String {
+    match <[String; 1]>::try_from(row) {
+        Ok([element]) => element,
+        Err(row) => format!("<mrow>{}</mrow>", row.concat()),
+    }
+}
+
+fn mo(c: &str) -> Node {
+    Node::new(format!("<mo>{}</mo>", escape_attr(c)))
+}
+
+fn mspace(width: &str) -> Node {
+    Node::new(format!("<mspace width=\"{width}\"></mspace>"))
+}
+
+const FUNCTIONS: [&str; 27] = [
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "det", "gcd", "deg", "arg", "dim", "ker", "lim", "max", "min", "sup",
+    "inf",
+];
+
+// functions whose subscripts go underneath in display math, e.g. `\lim_{x \to 0}`
+const LIMIT_FUNCTIONS: [&str; 6] = ["lim", "max", "min", "sup", "inf", "gcd"];
+
+fn greek(name: &str) -> Option<char> {
+    Some(match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        _ => return None,
+    })
+}
+
+// identifiers that aren't letters
+fn symbol(name: &str) -> Option<char> {
+    Some(match name {
+        "infty" => '∞',
+        "partial" => '∂',
+        "nabla" => '∇',
+        "emptyset" => '∅',
+        "hbar" => 'ℏ',
+        "ell" => 'ℓ',
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "cdot" => "⋅",
+        "times" => "×",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "circ" => "∘",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "iff" => "⟺",
+        "implies" => "⟹",
+        "mapsto" => "↦",
+        "in" => "∈",
+        "notin" => "∉",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "forall" => "∀",
+        "exists" => "∃",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "mid" => "∣",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "lbrace" => "{",
+        "rbrace" => "}",
+        "vert" => "|",
+        "Vert" => "‖",
+        _ => return None,
+    })
+}
+
+fn large_operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        _ => return None,
+    })
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+// `---` on a line of its own, opening and closing front matter
+pub const FRONT_MATTER_FENCE: &[u8] = b"---";
+
+// `key: value` pairs from front matter
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+    // `tags: a, b` -> ["a", "b"]
+    pub fn list(&self, key: &str) -> Vec<&str> {
+        self.get(key)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        self.0.insert(key, value)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// `---` lines around `key: value` lines at the very start of `input`; returns the metadata
+// and the index just past the closing `---` line
+pub fn front_matter(input: &[u8]) -> Option<(Metadata, usize)> {
+    let mut lines = input.split_inclusive(|c| *c == b'\n');
+    let mut end = lines.next().filter(|line| is_fence(line))?.len();
+    let mut metadata = Metadata::new();
+    for line in lines {
+        end += line.len();
+        if is_fence(line) {
+            return Some((metadata, end));
+        }
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            continue;
+        }
+        let colon = line.iter().position(|c| *c == b':')?;
+        let key = line[..colon].trim_ascii();
+        if key.is_empty() || key.iter().any(u8::is_ascii_whitespace) {
+            return None;
+        }
+        let value = line[colon + 1..].trim_ascii();
+        // "quoted" or 'quoted'
+        let value = match value {
+            [b'"', value @ .., b'"'] | [b'\'', value @ .., b'\''] => value,
+            value => value,
+        };
+        metadata.insert(
+            String::from_utf8_lossy(key).into_owned(),
+            String::from_utf8_lossy(value).into_owned(),
+        );
+    }
+    // never closed, so not front matter after all
+    None
+}
+
+fn is_fence(line: &[u8]) -> bool {
+    line.trim_ascii_end() == FRONT_MATTER_FENCE
+}
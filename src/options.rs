@@ -0,0 +1,225 @@
+use crate::url_scheme;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    // one block element per line, nested blocks indented
+    #[default]
+    Pretty,
+    // no whitespace between blocks
+    Minified,
+}
+
+// schemes bare urls are linked for by default
+pub const AUTOLINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+// schemes links may use by default; urls without one are always allowed
+pub const LINK_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+// `!!! type` callouts recognized by default
+pub const CALLOUTS: [&str; 3] = ["note", "warning", "danger"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisallowedLinks {
+    // keep the link's label (or url) as plain text
+    #[default]
+    Text,
+    // leave the link out entirely
+    Drop,
+}
+
+// rewrites every link and image url before it's written
+#[derive(Clone)]
+pub struct LinkRewriter(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl LinkRewriter {
+    pub fn new<F: Fn(&str) -> String + Send + Sync + 'static>(rewrite: F) -> Self {
+        Self(Arc::new(rewrite))
+    }
+    // `other.samup` -> `{base_url}other.html`; urls with a scheme and `#fragment`s are left alone
+    pub fn samup_to_html(base_url: &str) -> Self {
+        let base_url = base_url.to_string();
+        Self::new(move |url| {
+            if url_scheme(url).is_some() || url.starts_with('#') || url.starts_with("//") {
+                return url.to_string();
+            }
+            let path_end = url.find(['?', '#']).unwrap_or(url.len());
+            let (path, rest) = url.split_at(path_end);
+            let path = match path.strip_suffix(".samup") {
+                Some(stem) => format!("{stem}.html"),
+                None => path.to_string(),
+            };
+            resolve(&base_url, &format!("{path}{rest}"))
+        })
+    }
+    pub fn rewrite(&self, url: &str) -> String {
+        (self.0)(url)
+    }
+}
+
+impl std::fmt::Debug for LinkRewriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LinkRewriter")
+    }
+}
+
+// `url` relative to `base_url`, which is always a directory, with or without a trailing `/`:
+// `/a` is relative to its origin, `a` to the directory itself
+fn resolve(base_url: &str, url: &str) -> String {
+    if base_url.is_empty() {
+        return url.to_string();
+    }
+    let origin_end = match base_url.find("://") {
+        Some(ix) => base_url[ix + 3..]
+            .find('/')
+            .map_or(base_url.len(), |len| ix + 3 + len),
+        None => 0,
+    };
+    match url.strip_prefix('/') {
+        Some(path) => format!("{}/{path}", &base_url[..origin_end]),
+        None => format!("{}/{url}", base_url.trim_end_matches('/')),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub output_mode: OutputMode,
+    // append a `¶` link to each heading's own id
+    pub heading_anchors: bool,
+    // insert a table of contents before the first block
+    pub toc: bool,
+    // turn bare urls into links
+    pub autolink: bool,
+    // write `$...$` and `$$...$$` as mathml
+    pub math: bool,
+    // replace `:shortcode:`s like `:tada:` with their emoji
+    pub emoji: bool,
+    // url prefixes that count as bare urls, e.g. `https://` or `mailto:`
+    pub autolink_schemes: Vec<String>,
+    // url schemes links may use, e.g. `https`; anything else is a disallowed link
+    pub link_schemes: Vec<String>,
+    pub disallowed_links: DisallowedLinks,
+    pub rewrite_links: Option<LinkRewriter>,
+    // write `%%` comments as `<!-- -->` instead of dropping them
+    pub keep_comments: bool,
+    // write `~~~html` ... `~~~` blocks as is, instead of as escaped text
+    pub raw_html: bool,
+    // for untrusted input: never writes raw html, whatever `raw_html` says
+    pub safe: bool,
+    // `!!! type` callouts, e.g. `note`; anything else is a paragraph
+    pub callouts: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            output_mode: OutputMode::default(),
+            heading_anchors: false,
+            toc: false,
+            autolink: true,
+            math: true,
+            emoji: false,
+            autolink_schemes: AUTOLINK_SCHEMES.iter().map(|s| s.to_string()).collect(),
+            link_schemes: LINK_SCHEMES.iter().map(|s| s.to_string()).collect(),
+            disallowed_links: DisallowedLinks::default(),
+            rewrite_links: None,
+            keep_comments: false,
+            raw_html: false,
+            safe: false,
+            callouts: CALLOUTS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+    pub fn heading_anchors(mut self, heading_anchors: bool) -> Self {
+        self.heading_anchors = heading_anchors;
+        self
+    }
+    pub fn toc(mut self, toc: bool) -> Self {
+        self.toc = toc;
+        self
+    }
+    pub fn autolink(mut self, autolink: bool) -> Self {
+        self.autolink = autolink;
+        self
+    }
+    pub fn math(mut self, math: bool) -> Self {
+        self.math = math;
+        self
+    }
+    pub fn emoji(mut self, emoji: bool) -> Self {
+        self.emoji = emoji;
+        self
+    }
+    pub fn autolink_schemes<I, S>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.autolink_schemes = schemes.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn link_schemes<I, S>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.link_schemes = schemes.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn disallowed_links(mut self, disallowed_links: DisallowedLinks) -> Self {
+        self.disallowed_links = disallowed_links;
+        self
+    }
+    pub fn rewrite_links<F: Fn(&str) -> String + Send + Sync + 'static>(
+        mut self,
+        rewrite: F,
+    ) -> Self {
+        self.rewrite_links = Some(LinkRewriter::new(rewrite));
+        self
+    }
+    // links to other `.samup` files point at their `.html`, under the `base_url` directory if
+    // it isn't empty
+    pub fn samup_links(mut self, base_url: &str) -> Self {
+        self.rewrite_links = Some(LinkRewriter::samup_to_html(base_url));
+        self
+    }
+    pub fn keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+        self
+    }
+    pub fn raw_html(mut self, raw_html: bool) -> Self {
+        self.raw_html = raw_html;
+        self
+    }
+    pub fn safe(mut self, safe: bool) -> Self {
+        self.safe = safe;
+        self
+    }
+    pub fn callouts<I, S>(mut self, callouts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.callouts = callouts.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn allows_raw_html(&self) -> bool {
+        self.raw_html && !self.safe
+    }
+    pub fn minified() -> Self {
+        Self::new().output_mode(OutputMode::Minified)
+    }
+    // for untrusted input
+    pub fn untrusted() -> Self {
+        Self::new().safe(true)
+    }
+}
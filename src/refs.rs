@@ -0,0 +1,38 @@
+use crate::unquote;
+use std::collections::HashMap;
+
+// `[ref]: url`; returns the (normalized) ref and the url
+pub fn definition(line: &[u8]) -> Option<(String, String)> {
+    let line = line.trim_ascii();
+    let rest = line.strip_prefix(b"[")?;
+    let end = rest.iter().position(|c| *c == b']')?;
+    let (name, rest) = (&rest[..end], &rest[end + 1..]);
+    // [^1]: is a footnote
+    if name.first().is_none_or(|c| *c == b'^') || name.contains(&b'[') {
+        return None;
+    }
+    let url = rest.strip_prefix(b":")?.trim_ascii();
+    if url.is_empty() || url.iter().any(u8::is_ascii_whitespace) {
+        return None;
+    }
+    Some((normalize(name), String::from_utf8_lossy(url).into_owned()))
+}
+
+// every definition in `input`; the first definition of a ref wins
+pub fn definitions(input: &[u8]) -> HashMap<String, String> {
+    let mut definitions = HashMap::new();
+    let lines = input.split(|c| *c == b'\n').map(unquote);
+    for (name, url) in lines.filter_map(definition) {
+        definitions.entry(name).or_insert(url);
+    }
+    definitions
+}
+
+// refs are case-insensitive, and runs of whitespace in them are insignificant
+pub fn normalize(name: &[u8]) -> String {
+    String::from_utf8_lossy(name)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
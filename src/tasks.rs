@@ -0,0 +1,24 @@
+// `- [ ] task` or `- [x] done`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Task {
+    // 1-based
+    pub line: usize,
+    pub done: bool,
+    // as plain text, without any markup, like `Heading::text`
+    pub text: String,
+}
+
+// `- [ ] ` or `- [x] ` starting `line`; returns whether the task is done and the length of
+// the marker, along with the whitespace after it
+pub fn task_marker(line: &[u8]) -> Option<(bool, usize)> {
+    let done = match line.get(..5)? {
+        b"- [ ]" => false,
+        b"- [x]" | b"- [X]" => true,
+        _ => return None,
+    };
+    let spaces = line[5..]
+        .iter()
+        .take_while(|c| c.is_ascii_whitespace())
+        .count();
+    (spaces > 0 || line.len() == 5).then_some((done, 5 + spaces))
+}
@@ -0,0 +1,105 @@
+use crate::{OutputMode, SamupResult, unquote, write_escaped};
+use std::io::Write;
+
+// [[toc]]
+pub const TOC_DIRECTIVE: &[u8] = b"[[toc]]";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub id: String,
+    // as plain text, without any markup, like `Task::text`
+    pub text: String,
+}
+
+pub fn is_toc_directive(line: &[u8]) -> bool {
+    line.trim_ascii() == TOC_DIRECTIVE
+}
+
+pub fn has_toc_directive(input: &[u8]) -> bool {
+    input
+        .split(|c| *c == b'\n')
+        .any(|line| is_toc_directive(unquote(line)))
+}
+
+// nested `<nav><ol>` of `headings`, indented `depth` levels in pretty output
+pub fn write_toc<O: Write>(
+    headings: &[Heading],
+    output: &mut O,
+    output_mode: OutputMode,
+    depth: usize,
+) -> SamupResult {
+    if headings.is_empty() {
+        return Ok(());
+    }
+    let pretty = output_mode == OutputMode::Pretty;
+    let indent = |output: &mut O, depth: usize| -> SamupResult {
+        if pretty {
+            output.write_all("  ".repeat(depth).as_bytes())?;
+        }
+        Ok(())
+    };
+    let newline = |output: &mut O| -> SamupResult {
+        if pretty {
+            output.write_all(b"\n")?;
+        }
+        Ok(())
+    };
+    // <nav> <ol> <li> <ol> <li> ...
+    let ol_depth = |n: usize| depth + 2 * n - 1;
+    indent(output, depth)?;
+    output.write_all(b"<nav class=\"toc\">")?;
+    newline(output)?;
+    // level of the first heading in each open <ol>
+    let mut levels: Vec<u8> = Vec::new();
+    for heading in headings {
+        match levels.last_mut() {
+            Some(level) if heading.level <= *level => {
+                output.write_all(b"</li>")?;
+                newline(output)?;
+                while levels.len() > 1 && heading.level <= levels[levels.len() - 2] {
+                    levels.pop();
+                    indent(output, ol_depth(levels.len() + 1))?;
+                    output.write_all(b"</ol>")?;
+                    newline(output)?;
+                    indent(output, ol_depth(levels.len()) + 1)?;
+                    output.write_all(b"</li>")?;
+                    newline(output)?;
+                }
+                if let Some(level) = levels.last_mut() {
+                    *level = (*level).min(heading.level);
+                }
+            }
+            _ => {
+                if !levels.is_empty() {
+                    newline(output)?;
+                }
+                levels.push(heading.level);
+                indent(output, ol_depth(levels.len()))?;
+                output.write_all(b"<ol>")?;
+                newline(output)?;
+            }
+        }
+        let Heading { id, text, .. } = heading;
+        indent(output, ol_depth(levels.len()) + 1)?;
+        write!(output, "<li><a href=\"#{id}\">")?;
+        write_escaped(output, text.as_bytes())?;
+        output.write_all(b"</a>")?;
+    }
+    output.write_all(b"</li>")?;
+    newline(output)?;
+    while !levels.is_empty() {
+        indent(output, ol_depth(levels.len()))?;
+        output.write_all(b"</ol>")?;
+        newline(output)?;
+        levels.pop();
+        if !levels.is_empty() {
+            indent(output, ol_depth(levels.len()) + 1)?;
+            output.write_all(b"</li>")?;
+            newline(output)?;
+        }
+    }
+    indent(output, depth)?;
+    output.write_all(b"</nav>")?;
+    newline(output)
+}
@@ -1,1317 +1,1483 @@
-use crate::{C, InnerLink, LinkState, SamupResult, Tag};
-use std::collections::VecDeque;
+use crate::emoji;
+use crate::math;
+use crate::metadata::{self, Metadata};
+use crate::refs;
+use crate::tasks::{self, Task};
+use crate::toc::{self, Heading};
+use crate::{
+    Align, C, Diagnostic, DisallowedLinks, FootNoteIx, HLevel, InnerImage, InnerLink, InnerTable,
+    LinkState, Options, OutputMode, SamupResult, Tag, slugify, strip_tags, unescape, url_scheme,
+    write_escaped,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+
+// `~~~html` and `~~~` lines around raw html
+const RAW_OPEN: &[u8] = b"~~~html";
+const RAW_CLOSE: &[u8] = b"~~~";
+// `%% ...`
+const COMMENT: &[u8] = b"%%";
+// `%%%` lines around a multiline comment
+const COMMENT_FENCE: &[u8] = b"%%%";
+// `!!! type` and `!!!` lines around a callout
+const CALLOUT_FENCE: &[u8] = b"!!!";
+// `+++ summary` and `+++` lines around a collapsible section
+const DETAILS_FENCE: &[u8] = b"+++";
+// the most blockquotes, callouts and collapsible sections open at once
+const MAX_NESTING: usize = 64;
+// the most inline tags open at once inside a block
+const MAX_INLINE_NESTING: usize = 64;
 
 #[derive(Debug)]
 pub struct Transcriber {
     pub ix: usize,
     prev_c: C,
     tag_stack: VecDeque<Tag>,
+    // number of inline tags at the front of `tag_stack`, opened since the innermost block
+    inline_depth: usize,
+    options: Options,
+    // only transcribe inline markup, without opening any blocks
+    inline: bool,
+    // inline markup inside a link's label, which can't hold any links of its own
+    link_label: bool,
+    heading_ids: HashSet<String>,
+    // the next `-n` suffix to try for each slug already taken
+    heading_suffixes: HashMap<String, usize>,
+    headings: Vec<Heading>,
+    tasks: Vec<Task>,
+    // every heading in the document, if known ahead of time
+    toc: Option<Vec<Heading>>,
+    // `[ref]: url`s, by normalized ref
+    // shared with the transcribers for headings, table cells and the like
+    definitions: Arc<HashMap<String, String>>,
+    diagnostics: Vec<Diagnostic>,
+    metadata: Metadata,
+    // the last line ended in `\` or two spaces
+    hard_break: bool,
+    // the index and line number of the last line looked up, to count on from
+    line_cursor: (usize, usize),
+    // inline tags closed by `close_inline` and not yet reopened, outermost first
+    reopen: Vec<Tag>,
+    // where the last failed scans for the `]` ending a link url and the `)` ending a link
+    // label looked, so later `[`s and `(`s there don't look again
+    unclosed_url: Range<usize>,
+    unclosed_label: Range<usize>,
+    // where the last failed scan for the `$` ending `$` math looked
+    unclosed_math: Range<usize>,
+    // where the last failed scan for the `+++` closing a bare `+++` looked
+    unclosed_details: Range<usize>,
+    // the last `~` or `^` looked for to close a sub or sup, the starts it answers for, and
+    // whether it was found
+    script_closer: (u8, Range<usize>, bool),
 }
 
 impl Transcriber {
     pub fn new() -> Self {
+        Self::with_options(Options::default())
+    }
+    pub fn with_options(options: Options) -> Self {
         Self {
             ix: 0,
             prev_c: C::Newline,
             tag_stack: VecDeque::new(),
+            inline_depth: 0,
+            options,
+            inline: false,
+            link_label: false,
+            heading_ids: HashSet::new(),
+            heading_suffixes: HashMap::new(),
+            headings: Vec::new(),
+            tasks: Vec::new(),
+            toc: None,
+            definitions: Arc::default(),
+            diagnostics: Vec::new(),
+            metadata: Metadata::new(),
+            hard_break: false,
+            line_cursor: (0, 1),
+            reopen: Vec::new(),
+            unclosed_url: 0..0,
+            unclosed_label: 0..0,
+            unclosed_math: 0..0,
+            unclosed_details: 0..0,
+            script_closer: (0, 0..0, false),
+        }
+    }
+    pub fn with_toc(mut self, toc: Vec<Heading>) -> Self {
+        self.toc = Some(toc);
+        self
+    }
+    pub fn with_definitions(mut self, definitions: HashMap<String, String>) -> Self {
+        self.definitions = Arc::new(definitions);
+        self
+    }
+    // the headings transcribed so far
+    pub fn headings(&self) -> &[Heading] {
+        &self.headings
+    }
+    pub fn into_headings(self) -> Vec<Heading> {
+        self.headings
+    }
+    // the tasks transcribed so far
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+    pub fn into_tasks(self) -> Vec<Task> {
+        self.tasks
+    }
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+    // the front matter, once transcription has started
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    fn inline(options: Options) -> Self {
+        Self {
+            prev_c: C::Whitespace,
+            inline: true,
+            ..Self::with_options(options)
         }
     }
     pub fn transcribe<O: Write>(&mut self, input: &[u8], output: &mut O) -> SamupResult {
+        if self.ix == 0 && !self.inline {
+            if let Some((metadata, end)) = metadata::front_matter(input) {
+                self.metadata = metadata;
+                self.ix = end;
+            }
+            if self.options.toc {
+                self.write_toc(output)?;
+            }
+            if self.ix >= input.len() {
+                return Ok(());
+            }
+        }
+        if self.prev_c == C::Newline
+            && C::from(input[self.ix]) != C::Newline
+            && let Some(next_c) = self.transcribe_line_start(input, output)?
+        {
+            self.prev_c = next_c;
+            self.ix += 1;
+            return Ok(());
+        }
         let curr_char = input[self.ix];
         let curr_c: C = curr_char.into();
         let next_c = match curr_c {
-            C::Whitespace => self.transcribe_whitespace(curr_char, output)?,
-            C::Newline => self.transcribe_newline(curr_char, output)?,
-            C::Underscore => self.transcribe_underscore(output)?,
-            C::Asterisk => self.transcribe_asterisk(output)?,
-            C::Octothorpe => self.transcribe_octothorpe(output)?,
-            C::Caret => self.transcribe_caret(output)?,
-            C::Colon => self.transcribe_colon(output)?,
-            C::SqBracketL => self.transcribe_sq_bracket_l(output)?,
-            C::SqBracketR => self.transcribe_sq_bracket_r(output)?,
-            C::ParenL | C::ParenR => self.transcribe_paren(output)?,
-            C::Digit => self.transcribe_digit(curr_char, output)?,
-            C::Content => self.transcribe_content(curr_char, output)?,
+            C::Whitespace => self.transcribe_whitespace(input, output)?,
+            C::Backslash => self.transcribe_backslash(curr_char, input, output)?,
+            C::Newline => self.transcribe_newline(input, output)?,
+            C::Underscore | C::Asterisk | C::Tilde | C::Plus | C::Caret => {
+                self.transcribe_emphasis(input, output)?
+            }
+            C::SqBracketL => self.transcribe_sq_bracket_l(input, output)?,
+            C::ParenR => self.transcribe_paren_r(curr_char, input, output)?,
+            C::Bang => self.transcribe_bang(curr_char, input, output)?,
+            C::Dollar => self.transcribe_dollar(curr_char, input, output)?,
+            C::Colon => self.transcribe_colon(curr_char, input, output)?,
+            C::Octothorpe | C::SqBracketR | C::ParenL | C::Gt | C::Pipe | C::Percent | C::Digit => {
+                self.transcribe_content(curr_char, output)?
+            }
+            C::Content => self.transcribe_autolink(curr_char, input, output)?,
         };
         self.prev_c = next_c.unwrap_or(curr_c);
         self.ix += 1;
         Ok(())
     }
     pub fn finish<O: Write>(&mut self, output: &mut O) -> SamupResult {
-        match self.prev_c {
-            C::Whitespace | C::Newline | C::Content => (),
-            C::Underscore => {
-                if let Some(tag @ Tag::I) = self.pop_tag() {
-                    tag.write_close(output)?
-                } else {
-                    output.write_all(b"_")?;
+        while !self.stack_empty() {
+            self.close_block(output)?;
+        }
+        self.reopen.clear();
+        Ok(())
+    }
+    // called on the first character of every line; returns `Some` if it consumed
+    // the whole line, otherwise transcription continues from the line's content
+    fn transcribe_line_start<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let hard_break = std::mem::take(&mut self.hard_break);
+        // leading whitespace is insignificant
+        let (quotes, start) = quote_markers(input, self.ix);
+        let eol = line_end(input, start);
+        // `> a\nb` continues the quoted paragraph
+        let lazy = quotes < self.quote_depth()
+            && start < eol
+            && matches!(self.block(), Some(Tag::P))
+            && !starts_block(&input[start..eol]);
+        if !lazy {
+            self.close_quotes(quotes, output)?;
+            if quotes > self.quote_depth() {
+                if self.leaf().is_some() {
+                    self.close_block(output)?;
+                }
+                while self.in_dl() || self.in_task_list() {
+                    self.close_block(output)?;
+                }
+                while self.quote_depth() < quotes && !self.nested_too_deeply(input) {
+                    self.open_block(Tag::Blockquote, output)?;
                 }
             }
-            C::Asterisk => {
-                if let Some(tag @ Tag::Strong) = self.pop_tag() {
-                    tag.write_close(output)?;
-                } else {
-                    output.write_all(b"*")?;
-                }
+        }
+        if start == eol {
+            // nothing but whitespace and quote markers ends the paragraph
+            if self.leaf().is_some() {
+                self.close_block(output)?;
+            }
+            self.ix = newline_end(input, eol);
+            return Ok(Some(C::Newline));
+        }
+        self.ix = start;
+        if matches!(self.block(), Some(Tag::Table(_))) && C::from(input[start]) != C::Pipe {
+            self.close_block(output)?;
+        }
+        if let Some(next_c) = self.transcribe_task(input, eol, output)? {
+            return Ok(Some(next_c));
+        }
+        if let Some(next_c) = self.transcribe_callout(input, eol, output)? {
+            return Ok(Some(next_c));
+        }
+        if let Some(next_c) = self.transcribe_details(input, quotes, eol, output)? {
+            return Ok(Some(next_c));
+        }
+        if let Some(next_c) = self.transcribe_definition_list(input, quotes, eol, output)? {
+            return Ok(Some(next_c));
+        }
+        match C::from(input[self.ix]) {
+            _ if is_thematic_break(&input[start..eol]) => {
+                if self.leaf().is_some() {
+                    self.close_block(output)?;
+                }
+                self.open_block(Tag::Hr, output)?;
+                self.close_block(output)?;
+                self.ix = eol - 1;
+                return Ok(Some(C::Content));
+            }
+            C::Percent if input[start..eol].starts_with(COMMENT) => {
+                return self.transcribe_comment(input, quotes, eol, output);
+            }
+            _ if input[start..eol].trim_ascii_end() == RAW_OPEN => {
+                return self.transcribe_raw(input, quotes, eol, output);
             }
             C::Octothorpe => {
-                if let Some(Tag::H(mut n)) = self.pop_tag() {
-                    let inced = n.inc_level();
-                    output.write_all(n.as_octothorpes())?;
-                    if !inced {
-                        output.write_all(b"#")?;
-                    }
-                } else {
-                    output.write_all(b"#")?;
+                if let Some(next_c) = self.transcribe_heading(input, output)? {
+                    return Ok(Some(next_c));
                 }
             }
-            C::Caret => {
-                output.write_all(b"[^")?;
+            C::SqBracketL if toc::is_toc_directive(&input[start..eol]) => {
+                if self.leaf().is_some() {
+                    self.close_block(output)?;
+                }
+                self.write_toc(output)?;
+                self.ix = eol - 1;
+                return Ok(Some(C::Content));
             }
-            C::Colon => {
-                if let Some(Tag::FootNoteRef(n)) = self.pop_tag() {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]:"))?;
-                } else {
-                    output.write_all(b":")?;
+            // [ref]: url
+            C::SqBracketL if refs::definition(&input[start..eol]).is_some() => {
+                if self.leaf().is_some() {
+                    self.close_block(output)?;
                 }
+                self.ix = eol - 1;
+                return Ok(Some(C::Content));
             }
             C::SqBracketL => {
-                output.write_all(b"[")?;
-            }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                    tag.write_link_no_title(output)?;
+                if let Some(next_c) = self.transcribe_foot_note_ref(input, output)? {
+                    return Ok(Some(next_c));
                 }
-                Some(tag @ Tag::FootNoteLink(_)) | Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_close(output)?;
-                }
-                _ => output.write_all(b"]")?,
-            },
-            C::ParenL => {
-                if let Some(tag @ Tag::Link(_)) = self.pop_tag() {
-                    tag.write_link_no_title(output)?;
-                };
-                output.write_all(b"(")?;
             }
-            C::ParenR => {
-                if let Some(tag @ Tag::Link(_)) = self.pop_tag() {
-                    tag.write_close(output)?;
-                } else {
-                    output.write_all(b")")?;
+            C::Pipe => {
+                if let Some(next_c) = self.transcribe_table_row(input, quotes, eol, output)? {
+                    return Ok(Some(next_c));
                 }
             }
-            C::Digit => {
-                if let Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) = self.pop_tag() {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}"))?;
+            C::Dollar if self.options.math && input[start..eol].starts_with(b"$$") => {
+                if let Some(next_c) = self.transcribe_display_math(input, output)? {
+                    return Ok(Some(next_c));
                 }
             }
-        };
-        while let Some(tag) = self.pop_tag() {
-            match tag {
-                Tag::H(_) | Tag::I | Tag::P | Tag::Strong | Tag::FootNoteRef(_) => {
-                    tag.write_close(output)?;
-                }
-                Tag::Link(u) => {
-                    output.write_fmt(format_args!("[{u}"))?;
-                }
-                Tag::FootNoteLink(n) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]"))?;
-                }
+            _ => (),
+        }
+        if let Some(Tag::P | Tag::Dd) = self.block() {
+            if hard_break {
+                output.write_all(b"<br>")?;
             }
+            // soft line break inside a paragraph
+            output.write_all(b"\n")?;
         }
-        Ok(())
+        self.prev_c = C::Whitespace;
+        Ok(None)
     }
-    fn transcribe_whitespace<O: Write>(
+    // `%% ...` to the end of the line, or `%%%` to the next `%%%` line (inside the same
+    // blockquote); a comment inside a paragraph doesn't end it
+    fn transcribe_comment<O: Write>(
         &mut self,
-        curr_char: u8,
+        input: &[u8],
+        quotes: usize,
+        eol: usize,
         output: &mut O,
     ) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace | C::Content => {
-                output.write_all(&[curr_char])?;
-            }
-            C::Newline => {
-                output.write_fmt(format_args!("\n{curr_char}"))?;
-            }
-            C::Underscore => match self.pop_tag() {
-                Some(Tag::I) => {
-                    Tag::I.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("_{curr_char}"))?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_fmt(format_args!("_{curr_char}"))?;
-                }
-            },
-            C::Asterisk => match self.pop_tag() {
-                Some(Tag::Strong) => {
-                    Tag::Strong.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("*{curr_char}"))?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_fmt(format_args!("*{curr_char}"))?;
-                }
-            },
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag)
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("#{curr_char}"))?;
-                    self.push_tag(tag)
-                }
-                None => output.write_fmt(format_args!("#{curr_char}"))?,
-            },
-            C::Caret => {
-                output.write_fmt(format_args!("[^{curr_char}"))?;
+        let (text, end) = if input[self.ix..eol].trim_ascii_end() == COMMENT_FENCE {
+            fenced(input, eol, COMMENT_FENCE, quotes)
+        } else {
+            (
+                input[self.ix + COMMENT.len()..eol].to_vec(),
+                newline_end(input, eol),
+            )
+        };
+        if self.options.keep_comments {
+            // `--` would end the comment early
+            let mut text = String::from_utf8_lossy(text.trim_ascii()).into_owned();
+            while text.contains("--") {
+                text = text.replace("--", "- -");
             }
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                    output.write_all(&[curr_char])?;
-                }
-                Some(other) => {
-                    output.write_fmt(format_args!(":{curr_char}"))?;
-                    self.push_tag(other);
-                }
-                None => {
-                    output.write_fmt(format_args!(":{curr_char}"))?;
-                }
-            },
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    output.write_fmt(format_args!("[{curr_char}"))?;
-                    self.push_tag(Tag::P);
-                } else {
-                    output.write_fmt(format_args!("[{curr_char}"))?
-                }
+            if let Some(Tag::P) = self.leaf() {
+                write!(output, "\n<!-- {text} -->")?;
+            } else {
+                self.write_indent(output)?;
+                write!(output, "<!-- {text} -->")?;
+                self.write_newline(output)?;
             }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                    tag.write_link_no_title(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(Tag::FootNoteLink(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]{curr_char}"))?
-                }
-                Some(other) => {
-                    output.write_fmt(format_args!("]{curr_char}"))?;
-                    self.push_tag(other);
-                }
-                None => output.write_fmt(format_args!("]{curr_char}"))?,
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(Tag::Link(ref url)) => {
-                    output.write_fmt(format_args!("[{url}]("))?;
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b"(")?;
-                }
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    tag.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(other) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(other);
-                }
-                None => output.write_all(&[curr_char])?,
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}{curr_char}"))?;
-                }
-                Some(t) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(t);
-                }
-                None => output.write_all(&[curr_char])?,
-            },
-        };
-        Ok(None)
+        }
+        self.ix = end;
+        Ok(Some(C::Newline))
     }
-    fn transcribe_newline<O: Write>(
+    // `~~~html` to the next `~~~` line (inside the same blockquote), written as is if
+    // `Options::raw_html` allows it, and as an escaped paragraph otherwise
+    fn transcribe_raw<O: Write>(
         &mut self,
-        curr_char: u8,
+        input: &[u8],
+        quotes: usize,
+        eol: usize,
         output: &mut O,
     ) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace | C::Content => (), // output.write_all(&[curr_char])?,
-            C::Newline => match self.pop_tag() {
-                Some(tag @ Tag::P) => {
-                    tag.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}{curr_char}"))?;
-                }
-                Some(tag) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(&[curr_char])?,
-            },
-            C::Colon => match self.pop_tag() {
-                Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]:{curr_char}"))?;
-                }
-                Some(tag) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(&[curr_char])?,
-            },
-            C::Underscore => match self.pop_tag() {
-                Some(Tag::I) => {
-                    Tag::I.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("_{curr_char}"))?;
-                    self.push_tag(tag)
-                }
-                None => output.write_fmt(format_args!("_{curr_char}"))?,
-            },
-            C::Asterisk => match self.pop_tag() {
-                Some(Tag::I) => {
-                    Tag::I.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("*{curr_char}"))?;
-                    self.push_tag(tag)
-                }
-                None => output.write_fmt(format_args!("*{curr_char}"))?,
-            },
-            C::Octothorpe => match self.pop_tag() {
-                Some(Tag::H(n)) => {
-                    output.write_all(n.as_octothorpes())?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("#{curr_char}"))?;
-                    self.push_tag(tag)
-                }
-                None => output.write_fmt(format_args!("#{curr_char}"))?,
-            },
-            C::Caret => {
-                output.write_fmt(format_args!("[^{curr_char}"))?;
-            }
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    output.write_fmt(format_args!("[{curr_char}"))?;
-                    self.push_tag(Tag::P);
-                } else {
-                    output.write_fmt(format_args!("[{curr_char}"))?;
-                }
-            }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                    tag.write_link_no_title(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(Tag::FootNoteLink(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]{curr_char}"))?
-                }
-                Some(tag) => {
-                    output.write_fmt(format_args!("]{curr_char}"))?;
-                    self.push_tag(tag);
-                }
-                None => output.write_fmt(format_args!("]{curr_char}"))?,
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(Tag::Link(ref url)) => {
-                    output.write_fmt(format_args!("[{url}]("))?;
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b"(")?;
-                }
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    tag.write_close(output)?;
-                    output.write_all(&[curr_char])?;
-                }
-                Some(other) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(other);
-                }
-                None => output.write_all(&[curr_char])?,
-            },
+        let (html, end) = fenced(input, eol, RAW_CLOSE, quotes);
+        let html = html.strip_suffix(b"\n").unwrap_or(&html);
+        let html = html.strip_suffix(b"\r").unwrap_or(html);
+        if self.leaf().is_some() {
+            self.close_block(output)?;
         }
-        Ok(None)
+        if self.options.allows_raw_html() {
+            output.write_all(html)?;
+            self.write_newline(output)?;
+        } else {
+            self.diagnose(input, String::from("raw html is disabled"));
+            self.open_block(Tag::P, output)?;
+            write_escaped(output, html)?;
+            self.close_block(output)?;
+        }
+        self.ix = end;
+        Ok(Some(C::Newline))
     }
-    fn transcribe_underscore<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace => {
-                Tag::I.write_open(output)?;
-                self.push_tag(Tag::I);
-            }
-            C::Newline => match self.pop_tag() {
-                Some(tag) => {
-                    output.write_all(b"\n")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::I);
-                }
-                None => {
-                    output.write_all(b"\n")?;
-                    Tag::P.write_open(output)?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(Tag::P);
-                    self.push_tag(Tag::I);
-                }
-            },
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                    Tag::I.write_open(output)?;
-                    self.push_tag(Tag::I);
-                }
-                Some(tag) => {
-                    output.write_all(b"#")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::I);
-                }
-                None => {
-                    output.write_all(b"#")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(Tag::I);
-                }
-            },
-            C::Caret => output.write_fmt(format_args!("[^"))?,
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::I)
-                }
-                Some(tag) => {
-                    output.write_all(b":")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::I)
-                }
-                None => {
-                    output.write_all(b":")?;
-                    Tag::P.write_open(output)?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(Tag::P);
-                    self.push_tag(Tag::I);
-                }
-            },
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                };
-                output.write_all(b"[")?;
-                Tag::I.write_open(output)?;
-                self.push_tag(Tag::I);
-            }
-            // __ -> _
-            C::Underscore | C::Content => (),
-            C::Asterisk => {
-                Tag::Strong.write_open(output)?;
-                self.push_tag(Tag::Strong);
-            }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    };
-                    tag.write_link_no_title(output)?;
-                }
-                Some(tag @ Tag::FootNoteLink(_)) => {
-                    tag.write_open(output)?;
-                }
-                Some(tag) => {
-                    output.write_all(b"]")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"]")?,
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(Tag::Link(ref url)) => {
-                    output.write_fmt(format_args!("[{url}]("))?;
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b"(")?;
-                }
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => tag.write_close(output)?,
-                Some(tag) => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b")")?;
-                }
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]"))?;
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-                None => (),
-            },
+    // `!!! type title` to the next `!!!` line, around any other blocks; the title defaults to
+    // the capitalized type
+    fn transcribe_callout<O: Write>(
+        &mut self,
+        input: &[u8],
+        eol: usize,
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let line = &input[self.ix..eol];
+        if line.trim_ascii_end() == CALLOUT_FENCE
+            && self.close_fenced(|tag| matches!(tag, Tag::Callout(_)), output)?
+        {
+            self.ix = newline_end(input, eol);
+            return Ok(Some(C::Newline));
+        }
+        let Some((kind, title)) = callout(line) else {
+            return Ok(None);
         };
-        Ok(None)
+        let Some(kind) = self
+            .options
+            .callouts
+            .iter()
+            .find(|callout| callout.as_bytes().eq_ignore_ascii_case(kind))
+            .cloned()
+        else {
+            let kind = String::from_utf8_lossy(kind).into_owned();
+            self.diagnose(input, format!("unknown callout type {kind}"));
+            return Ok(None);
+        };
+        if self.nested_too_deeply(input) {
+            return Ok(None);
+        }
+        let title = if title.is_empty() {
+            let mut chars = kind.chars();
+            let title: String = chars
+                .next()
+                .into_iter()
+                .flat_map(char::to_uppercase)
+                .chain(chars)
+                .collect();
+            let mut escaped = Vec::new();
+            write_escaped(&mut escaped, title.as_bytes())?;
+            escaped
+        } else {
+            self.transcribe_inline(input, title)?
+        };
+        self.open_fenced(Tag::Callout(kind), output)?;
+        self.write_indent(output)?;
+        output.write_all(b"<p class=\"callout-title\">")?;
+        output.write_all(&title)?;
+        output.write_all(b"</p>")?;
+        self.write_newline(output)?;
+        self.ix = eol - 1;
+        Ok(Some(C::Content))
     }
-    fn transcribe_asterisk<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace => {
-                Tag::Strong.write_open(output)?;
-                self.push_tag(Tag::Strong);
-            }
-            C::Newline => match self.pop_tag() {
-                Some(tag) => {
-                    output.write_all(b"\n")?;
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::Strong);
-                }
-                None => {
-                    output.write_all(b"\n")?;
-                    Tag::P.write_open(output)?;
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(Tag::P);
-                    self.push_tag(Tag::Strong);
-                }
-            },
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(Tag::Strong);
-                }
-                Some(tag) => {
-                    output.write_all(b"#")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::Strong);
-                }
-                None => {
-                    output.write_all(b"#")?;
-                    Tag::I.write_open(output)?;
-                    self.push_tag(Tag::Strong);
-                }
-            },
-            C::Caret => output.write_fmt(format_args!("[^"))?,
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::Strong)
-                }
-                Some(tag) => {
-                    output.write_all(b":")?;
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(tag);
-                    self.push_tag(Tag::Strong)
-                }
-                None => {
-                    output.write_all(b":")?;
-                    Tag::P.write_open(output)?;
-                    Tag::Strong.write_open(output)?;
-                    self.push_tag(Tag::P);
-                    self.push_tag(Tag::Strong);
-                }
-            },
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                };
-                output.write_all(b"[")?;
-                Tag::Strong.write_open(output)?;
-                self.push_tag(Tag::Strong);
-            }
-            // ** -> *
-            C::Asterisk | C::Content => (),
-            C::Underscore => {
-                Tag::I.write_open(output)?;
-                self.push_tag(Tag::I);
-            }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    };
-                    tag.write_link_no_title(output)?;
-                }
-                Some(tag @ Tag::FootNoteLink(_)) => {
-                    tag.write_open(output)?;
-                }
-                Some(tag) => {
-                    output.write_all(b"]")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"]")?,
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(Tag::Link(ref url)) => {
-                    output.write_fmt(format_args!("[{url}]("))?;
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b"(")?;
-                }
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => tag.write_close(output)?,
-                Some(tag) => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b")")?;
-                }
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]"))?;
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-                None => (),
-            },
+    // `+++ summary` to the next `+++` line, around any other blocks; the summary defaults
+    // to `Details`
+    fn transcribe_details<O: Write>(
+        &mut self,
+        input: &[u8],
+        quotes: usize,
+        eol: usize,
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let line = &input[self.ix..eol];
+        // `+++` on its own opens a section if there isn't one to close
+        if line.trim_ascii_end() == DETAILS_FENCE
+            && self.close_fenced(|tag| *tag == Tag::Details, output)?
+        {
+            self.ix = newline_end(input, eol);
+            return Ok(Some(C::Newline));
+        }
+        let Some(summary) = details(line) else {
+            return Ok(None);
         };
-        Ok(None)
+        // a stray `+++` (like a front matter fence) would swallow everything after it
+        if summary.is_empty() && !self.details_closed(input, quotes, eol) {
+            return Ok(None);
+        }
+        if self.nested_too_deeply(input) {
+            return Ok(None);
+        }
+        let summary = if summary.is_empty() {
+            b"Details".to_vec()
+        } else {
+            self.transcribe_inline(input, summary)?
+        };
+        self.open_fenced(Tag::Details, output)?;
+        self.write_indent(output)?;
+        output.write_all(b"<summary>")?;
+        output.write_all(&summary)?;
+        output.write_all(b"</summary>")?;
+        self.write_newline(output)?;
+        self.ix = eol - 1;
+        Ok(Some(C::Content))
     }
-    fn transcribe_octothorpe<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Content | C::Whitespace => (),
-            C::Newline => {
-                match self.pop_tag() {
-                    Some(tag @ Tag::H(_)) => {
-                        tag.write_close(output)?;
-                        output.write_all(b"\n")?;
-                    }
-                    Some(tag) => {
-                        output.write_all(b"\n")?;
-                        self.push_tag(tag)
-                    }
-                    None => output.write_all(b"\n")?,
-                }
-                self.push_tag(Tag::new_h());
-                return Ok(Some(C::Octothorpe));
-            }
-            C::Octothorpe => match self.pop_tag() {
-                Some(mut tag @ Tag::H(_)) => {
-                    if !tag.inc_h() {
-                        tag.write_open(output)?;
-                        self.push_tag(tag);
-                    } else {
-                        self.push_tag(tag);
-                        return Ok(Some(C::Octothorpe));
-                    }
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-                None => (),
-            },
-            C::Asterisk => {
-                Tag::Strong.write_open(output)?;
-                output.write_all(b"#")?;
-                self.push_tag(Tag::Strong);
-            }
-            C::Underscore => {
-                Tag::I.write_open(output)?;
-                output.write_all(b"#")?;
-                self.push_tag(Tag::I);
+    // opens a callout or collapsible section, ending any paragraph or definition list
+    fn open_fenced<O: Write>(&mut self, tag: Tag, output: &mut O) -> SamupResult {
+        while self.leaf().is_some() || self.in_dl() {
+            self.close_block(output)?;
+        }
+        self.open_block(tag, output)
+    }
+    // closes blocks up to and including the innermost `is_fenced` one, if there's one
+    // inside the innermost blockquote
+    fn close_fenced<O: Write>(
+        &mut self,
+        is_fenced: impl Fn(&Tag) -> bool,
+        output: &mut O,
+    ) -> SamupResult<bool> {
+        let open = self
+            .tag_stack
+            .iter()
+            .take_while(|tag| **tag != Tag::Blockquote)
+            .any(&is_fenced);
+        if !open {
+            return Ok(false);
+        }
+        while self.block().is_some_and(|tag| !is_fenced(tag)) {
+            self.close_block(output)?;
+        }
+        self.close_block(output)?;
+        Ok(true)
+    }
+    // a term starting a block, followed by a line starting with `: `, then one or more
+    // `: definition`s, each of which may go on over several lines; blank lines in between
+    // don't end the list
+    fn transcribe_definition_list<O: Write>(
+        &mut self,
+        input: &[u8],
+        quotes: usize,
+        eol: usize,
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let line = &input[self.ix..eol];
+        let definition = definition_marker(line);
+        // a term starts a block, so a line going on with an open paragraph isn't one
+        let term = definition.is_none()
+            && !matches!(self.leaf(), Some(Tag::P))
+            && !starts_block(line)
+            && !line.starts_with(COMMENT)
+            && input
+                .get(eol..eol + 2)
+                .is_some_and(|nl| nl[0] == b'\n' || nl == b"\r\n")
+            && {
+                let next = newline_end(input, eol) + 1;
+                let (next_quotes, next_start) = quote_markers(input, next);
+                next_quotes == quotes
+                    && definition_marker(&input[next_start..line_end(input, next_start)]).is_some()
+            };
+        if self.in_dl() && definition.is_none() && !term {
+            // a definition may go on over several lines, like a paragraph
+            if matches!(self.leaf(), Some(Tag::Dd)) && !starts_block(line) {
+                return Ok(None);
             }
-            C::SqBracketL => {
-                match self.pop_tag() {
-                    None => {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                    Some(Tag::Link(_)) | Some(Tag::FootNoteRef(_)) | Some(Tag::FootNoteLink(_)) => {
-                    }
-                    Some(tag) => self.push_tag(tag),
-                }
-                output.write_all(b"[")?;
+            while self.in_dl() {
+                self.close_block(output)?;
             }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    };
-                    tag.write_link_no_title(output)?
-                }
-                Some(tag @ Tag::FootNoteLink(_)) => tag.write_open(output)?,
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b"]")?;
-                    self.push_tag(tag)
-                }
-                None => output.write_all(b"]")?,
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => self.push_tag(tag),
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"(")?,
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => tag.write_close(output)?,
-                Some(tag) => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b")")?,
-            },
-            C::Caret => {
-                match self.pop_tag() {
-                    None | Some(Tag::FootNoteLink(_)) | Some(Tag::FootNoteRef(_)) => (),
-                    Some(tag) => self.push_tag(tag),
-                }
-                output.write_all(b"^")?;
+            return Ok(None);
+        }
+        if let Some(len) = definition.filter(|_| self.in_dl()) {
+            if self.leaf().is_some() {
+                self.close_block(output)?;
             }
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b":")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b":")?,
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}"))?;
-                }
-                Some(tag) => self.push_tag(tag),
-                None => (),
-            },
+            self.open_block(Tag::Dd, output)?;
+            self.ix += len - 1;
+            return Ok(Some(C::Whitespace));
+        }
+        if !term {
+            return Ok(None);
+        }
+        if self.leaf().is_some() {
+            self.close_block(output)?;
         }
-        output.write_all(b"#")?;
+        if !self.in_dl() {
+            self.open_block(Tag::Dl, output)?;
+        }
+        let content = self.transcribe_inline(input, line.trim_ascii_end())?;
+        self.open_block(Tag::Dt, output)?;
+        output.write_all(&content)?;
+        self.close_block(output)?;
+        self.ix = eol - 1;
         Ok(Some(C::Content))
     }
-    fn transcribe_caret<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        let mut next_c = None;
-        match self.prev_c {
-            C::SqBracketL => (),
-            _ => {
-                output.write_all(b"^")?;
-                next_c = Some(C::Content);
+    // `- [ ] task` or `- [x] done`, one per line; a run of them is a list, and blank lines in
+    // between don't end it
+    fn transcribe_task<O: Write>(
+        &mut self,
+        input: &[u8],
+        eol: usize,
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let Some((done, len)) = tasks::task_marker(&input[self.ix..eol]) else {
+            while self.in_task_list() {
+                self.close_block(output)?;
             }
+            return Ok(None);
         };
-        Ok(next_c)
+        while self.leaf().is_some() || self.in_dl() {
+            self.close_block(output)?;
+        }
+        if !self.in_task_list() {
+            self.open_block(Tag::Ul, output)?;
+        }
+        let content = self.transcribe_inline(input, input[self.ix + len..eol].trim_ascii_end())?;
+        let line = self.line_number(input);
+        self.tasks.push(Task {
+            line,
+            done,
+            text: unescape(&strip_tags(&content)),
+        });
+        self.open_block(Tag::Task(done), output)?;
+        output.write_all(&content)?;
+        self.close_block(output)?;
+        self.ix = eol - 1;
+        Ok(Some(C::Content))
     }
-    fn transcribe_colon<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::SqBracketR => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) => {
-                    self.push_tag(Tag::FootNoteRef(n));
-                    return Ok(None);
-                }
-                Some(tag) => {
-                    output.write_all(b"]:")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"]:")?,
-            },
-            _ => match self.pop_tag() {
-                Some(mut tag @ Tag::Link(_)) => {
-                    tag.push_link(":");
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b":")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b":")?,
-            },
+    fn transcribe_heading<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let Some((level, end)) = heading_level(input, self.ix) else {
+            return Ok(None);
         };
+        let eol = line_end(input, end);
+        let text = input[end..eol].trim_ascii_end();
+        if self.leaf().is_some() {
+            self.close_block(output)?;
+        }
+        let content = self.transcribe_inline(input, text)?;
+        let text = unescape(&strip_tags(&content));
+        let id = self.heading_id(&text);
+        self.headings.push(Heading {
+            level: level.level(),
+            id: id.clone(),
+            text,
+        });
+        self.open_block(Tag::H(level, id.clone()), output)?;
+        output.write_all(&content)?;
+        if self.options.heading_anchors {
+            write!(output, "<a class=\"anchor\" href=\"#{id}\">\u{b6}</a>")?;
+        }
+        self.close_block(output)?;
+        self.ix = eol - 1;
         Ok(Some(C::Content))
     }
-    fn transcribe_sq_bracket_l<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Underscore => {
-                Tag::I.write_open(output)?;
-                self.push_tag(Tag::I);
-            }
-            C::Asterisk => {
-                Tag::Strong.write_open(output)?;
-                self.push_tag(Tag::I);
-            }
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b"#")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"#")?,
-            },
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-                output.write_all(b"[")?;
-            }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    };
-                    tag.write_link_no_title(output)?;
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-                None => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                }
-            },
-            C::Caret => {
-                output.write_all(b"[^")?;
-            }
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    tag.write_close(output)?;
-                }
-                Some(tag) => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag);
-                }
-                None => (),
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}]"))?;
-                }
-                Some(tag) => self.push_tag(tag),
-                None => (),
-            },
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-                None => (),
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(Tag::Link(ref url)) => {
-                    output.write_fmt(format_args!("[{url}]("))?;
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b"(")?;
-                }
-            },
-            C::Newline => {
-                match self.pop_tag() {
-                    Some(tag @ Tag::H(_)) => {
-                        tag.write_close(output)?;
-                        output.write_all(b"\n")?;
-                    }
-                    Some(tag) => {
-                        output.write_all(b"\n")?;
-                        self.push_tag(tag);
-                    }
-                    None => (),
-                };
+    // slugified heading text, suffixed with `-1`, `-2`, ... if already taken
+    fn heading_id(&mut self, text: &str) -> String {
+        let slug = match slugify(text) {
+            slug if slug.is_empty() => String::from("section"),
+            slug => slug,
+        };
+        let mut id = slug.clone();
+        // `# a-1` may already have taken a suffixed id, so suffixes still need checking
+        let n = self.heading_suffixes.entry(slug.clone()).or_insert(0);
+        while self.heading_ids.contains(&id) {
+            *n += 1;
+            id = format!("{slug}-{n}");
+        }
+        self.heading_ids.insert(id.clone());
+        id
+    }
+    fn write_toc<O: Write>(&self, output: &mut O) -> SamupResult {
+        match &self.toc {
+            Some(headings) => {
+                toc::write_toc(headings, output, self.options.output_mode, self.depth())
             }
-            C::Content | C::Whitespace => (),
+            None => Ok(()),
+        }
+    }
+    // `text` is a single line of `input`, starting at `self.ix`
+    fn transcribe_inline(&mut self, input: &[u8], text: &[u8]) -> SamupResult<Vec<u8>> {
+        let link_label = self.in_link();
+        self.transcribe_inline_with(input, text, link_label)
+    }
+    // `transcribe_inline`, knowing whether `text` is a link's label
+    fn transcribe_inline_with(
+        &mut self,
+        input: &[u8],
+        text: &[u8],
+        link_label: bool,
+    ) -> SamupResult<Vec<u8>> {
+        let mut transcriber = Transcriber {
+            definitions: Arc::clone(&self.definitions),
+            link_label,
+            ..Transcriber::inline(self.options.clone())
         };
-        Ok(None)
+        let mut output = Vec::new();
+        while transcriber.ix < text.len() {
+            transcriber.transcribe(text, &mut output)?;
+        }
+        transcriber.finish(&mut output)?;
+        if transcriber.diagnostics.is_empty() {
+            return Ok(output);
+        }
+        let line = self.line_number(input);
+        self.diagnostics.extend(
+            transcriber
+                .diagnostics
+                .into_iter()
+                .map(|diagnostic| Diagnostic { line, ..diagnostic }),
+        );
+        Ok(output)
     }
-    fn transcribe_sq_bracket_r<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        let mut next_c: Option<C> = None;
-        match self.pop_tag() {
-            Some(
-                mut tag @ Tag::Link(InnerLink {
-                    state: LinkState::Link,
-                    ..
-                }),
-            ) => match self.prev_c {
-                C::Whitespace => {
-                    self.push_tag(tag);
-                }
-                C::Newline => {
-                    let url = tag.link_url();
-                    output.write_fmt(format_args!("{url}\n]"))?;
-                    return Ok(next_c);
-                }
-                C::Underscore => {
-                    tag.push_link("_");
-                    self.push_tag(tag);
-                }
-                C::Asterisk => {
-                    tag.push_link("*");
-                    self.push_tag(tag);
-                }
-                C::Caret => {
-                    tag.push_link("^");
-                    self.push_tag(tag);
-                }
-                C::Colon => {
-                    tag.push_link(":");
-                    self.push_tag(tag);
-                }
-                C::SqBracketL => {
-                    tag.push_link("[");
-                    self.push_tag(tag);
-                }
-                C::SqBracketR => {
-                    tag.push_link("]");
-                    self.push_tag(tag);
-                }
-                C::ParenL => {
-                    tag.push_link("(");
-                    self.push_tag(tag)
-                }
-                C::ParenR => {
-                    tag.push_link(")");
-                    self.push_tag(tag)
-                }
-                C::Octothorpe => {
-                    tag.push_link("#");
-                    self.push_tag(tag)
-                }
-                C::Digit | C::Content => self.push_tag(tag),
-            },
-            Some(
-                tag @ Tag::Link(InnerLink {
-                    state: LinkState::Label,
-                    ..
-                }),
-            ) => match self.prev_c {
-                C::Newline => {
-                    let url = tag.link_url();
-                    output.write_fmt(format_args!("{url}\n]"))?;
-                }
-                C::Underscore => {
-                    output.write_all(b"_")?;
-                    self.push_tag(tag);
-                }
-                C::Asterisk => {
-                    output.write_all(b"*")?;
-                    self.push_tag(tag);
-                }
-                C::Caret => {
-                    output.write_all(b"^")?;
-                    self.push_tag(tag);
-                }
-                C::Colon => {
-                    output.write_all(b":")?;
-                    self.push_tag(tag);
-                }
-                C::SqBracketL => {
-                    output.write_all(b"[")?;
-                    self.push_tag(tag);
-                }
-                C::SqBracketR => {
-                    output.write_all(b"]")?;
-                    self.push_tag(tag);
-                }
-                C::ParenL => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag)
-                }
-                C::ParenR => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag)
-                }
-                C::Octothorpe => {
-                    output.write_all(b"#")?;
-                    self.push_tag(tag)
+    // with its url rewritten, if there's a rewriter, and the original kept as its label
+    fn new_link(&self, state: LinkState, url: String) -> InnerLink {
+        InnerLink {
+            url: self.rewrite_url(url.clone()),
+            ..InnerLink::new(state, url)
+        }
+    }
+    fn rewrite_url(&self, url: String) -> String {
+        match &self.options.rewrite_links {
+            Some(rewriter) => rewriter.rewrite(&url),
+            None => url,
+        }
+    }
+    // whether `url` has no scheme or an allowed one, reporting it if not
+    fn allow_link(&mut self, input: &[u8], url: &str) -> bool {
+        // browsers ignore control characters, so `\x01javascript:` is still `javascript:`
+        let allowed = !url.chars().any(|c| c.is_control())
+            && url_scheme(url).is_none_or(|scheme| {
+                self.options
+                    .link_schemes
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+            });
+        if !allowed {
+            self.diagnose(input, format!("disallowed link url {url}"));
+        }
+        allowed
+    }
+    fn diagnose(&mut self, input: &[u8], message: String) {
+        let line = self.line_number(input);
+        self.diagnostics.push(Diagnostic { line, message });
+    }
+    // the line `self.ix` is on, counting on from the last one looked up
+    fn line_number(&mut self, input: &[u8]) -> usize {
+        let ix = self.ix.min(input.len());
+        let (from, line) = match self.line_cursor {
+            (from, line) if from <= ix => (from, line),
+            _ => (0, 1),
+        };
+        let line = line + input[from..ix].iter().filter(|c| **c == b'\n').count();
+        self.line_cursor = (ix, line);
+        line
+    }
+    fn transcribe_foot_note_ref<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let Some((Some(n), end)) = foot_note_ix(input, self.ix) else {
+            return Ok(None);
+        };
+        if input.get(end + 1) != Some(&b':') {
+            return Ok(None);
+        }
+        if self.leaf().is_some() {
+            self.close_block(output)?;
+        }
+        self.open_block(Tag::FootNoteRef(n), output)?;
+        self.ix = end + 1;
+        Ok(Some(C::Whitespace))
+    }
+    // `| a | b |`; the first row of a table is its header, and must be followed by
+    // a `|---|:-:|` separator row, quoted as deeply, giving each column's alignment
+    fn transcribe_table_row<O: Write>(
+        &mut self,
+        input: &[u8],
+        quotes: usize,
+        eol: usize,
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let cells = table_cells(&input[self.ix..eol]);
+        if let Some(Tag::Table(InnerTable { aligns, body })) = self.tag_stack.front() {
+            let (aligns, body) = (aligns.clone(), *body);
+            let depth = self.depth();
+            if !body {
+                self.indent(depth, output)?;
+                output.write_all(b"<tbody>")?;
+                self.write_newline(output)?;
+                if let Some(Tag::Table(table)) = self.tag_stack.front_mut() {
+                    table.body = true;
                 }
-                C::Digit | C::Content | C::Whitespace => self.push_tag(tag),
-            },
-
-            Some(tag @ Tag::FootNoteLink(_)) | Some(tag @ Tag::FootNoteRef(_)) => {
-                self.push_tag(tag);
             }
-            Some(tag) => {
-                output.write_all(b"]")?;
-                self.push_tag(tag);
-                next_c = Some(C::Content);
+            self.write_table_row(input, &cells, &aligns, "td", depth + 1, output)?;
+            self.ix = eol - 1;
+            return Ok(Some(C::Content));
+        }
+        let next = newline_end(input, eol) + 1;
+        if next >= input.len() {
+            return Ok(None);
+        }
+        let (separator_quotes, separator) = quote_markers(input, next);
+        if separator_quotes != quotes {
+            return Ok(None);
+        }
+        let separator_end = line_end(input, separator);
+        let Some(aligns) = table_aligns(&input[separator..separator_end])
+            .filter(|aligns| aligns.len() == cells.len())
+        else {
+            return Ok(None);
+        };
+        if self.leaf().is_some() {
+            self.close_block(output)?;
+        }
+        self.open_block(
+            Tag::Table(InnerTable {
+                aligns: aligns.clone(),
+                body: false,
+            }),
+            output,
+        )?;
+        let depth = self.depth();
+        self.indent(depth, output)?;
+        output.write_all(b"<thead>")?;
+        self.write_newline(output)?;
+        self.write_table_row(input, &cells, &aligns, "th", depth + 1, output)?;
+        self.indent(depth, output)?;
+        output.write_all(b"</thead>")?;
+        self.write_newline(output)?;
+        self.ix = separator_end - 1;
+        Ok(Some(C::Content))
+    }
+    fn write_table_row<O: Write>(
+        &mut self,
+        input: &[u8],
+        cells: &[&[u8]],
+        aligns: &[Align],
+        cell_tag: &str,
+        depth: usize,
+        output: &mut O,
+    ) -> SamupResult {
+        self.indent(depth, output)?;
+        output.write_all(b"<tr>")?;
+        self.write_newline(output)?;
+        // missing cells are left empty, extra cells are dropped
+        for (ix, align) in aligns.iter().enumerate() {
+            let cell = cells.get(ix).copied().unwrap_or_default();
+            self.indent(depth + 1, output)?;
+            match align.style() {
+                Some(align) => write!(output, "<{cell_tag} style=\"text-align: {align}\">")?,
+                None => write!(output, "<{cell_tag}>")?,
             }
-            None => {
-                Tag::P.write_open(output)?;
-                self.push_tag(Tag::P);
-                output.write_all(b"]")?;
-                next_c = Some(C::Content);
+            output.write_all(&self.transcribe_inline(input, cell)?)?;
+            write!(output, "</{cell_tag}>")?;
+            self.write_newline(output)?;
+        }
+        self.indent(depth, output)?;
+        output.write_all(b"</tr>")?;
+        self.write_newline(output)
+    }
+    fn transcribe_whitespace<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        self.ensure_block(output)?;
+        let run = input[self.ix..]
+            .iter()
+            .take_while(|c| C::from(**c) == C::Whitespace)
+            .count();
+        // two or more trailing spaces are a hard line break instead
+        if run >= 2
+            && input
+                .get(self.ix + run)
+                .is_some_and(|c| C::from(*c) == C::Newline)
+        {
+            self.ix += run - 1;
+            return Ok(None);
+        }
+        // the whole run at once, so it isn't counted again for every character in it
+        output.write_all(&input[self.ix..self.ix + run])?;
+        self.ix += run - 1;
+        Ok(None)
+    }
+    // `\` at the end of a line is a hard line break
+    fn transcribe_backslash<O: Write>(
+        &mut self,
+        curr_char: u8,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        match input.get(self.ix + 1..) {
+            Some([b'\n', ..] | [b'\r', b'\n', ..]) => Ok(None),
+            // `\$` is a plain `$`
+            Some([b'$', ..]) if self.options.math => {
+                self.ix += 1;
+                self.transcribe_content(b'$', output)?;
+                Ok(Some(C::Content))
             }
+            _ => self.transcribe_content(curr_char, output),
         }
+    }
+    // `$tex$` or `$$tex$$` as mathml, with nothing inside taken as markup
+    fn transcribe_dollar<O: Write>(
+        &mut self,
+        curr_char: u8,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        if !self.options.math {
+            return self.transcribe_content(curr_char, output);
+        }
+        let display = input.get(self.ix + 1) == Some(&b'$');
+        let end = if display {
+            math_end(input, self.ix, true).ok()
+        } else {
+            self.inline_math_end(input)
+        };
+        let Some(end) = end else {
+            if display {
+                // not `$` followed by `$tex$`
+                self.ensure_block(output)?;
+                output.write_all(b"$$")?;
+                self.ix += 1;
+                return Ok(Some(C::Content));
+            }
+            return self.transcribe_content(curr_char, output);
+        };
+        self.ensure_block(output)?;
+        self.write_math(input, end, display, output)?;
+        self.ix = end;
+        Ok(Some(C::Content))
+    }
+    // a line starting with `$$tex$$`, which may go on over several lines, is a block of its own
+    fn transcribe_display_math<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let Ok(end) = math_end(input, self.ix, true) else {
+            return Ok(None);
+        };
+        let eol = line_end(input, end);
+        if !input[end + 1..eol].trim_ascii().is_empty() {
+            return Ok(None);
+        }
+        while self.leaf().is_some() || self.in_dl() {
+            self.close_block(output)?;
+        }
+        self.write_indent(output)?;
+        self.write_math(input, end, true, output)?;
+        self.write_newline(output)?;
+        self.ix = eol - 1;
+        Ok(Some(C::Content))
+    }
+    // `details_close` for the bare `+++` at `self.ix`, unless an earlier scan already failed
+    // there
+    fn details_closed(&mut self, input: &[u8], quotes: usize, eol: usize) -> bool {
+        if self.unclosed_details.contains(&self.ix) {
+            return false;
+        }
+        details_close(input, quotes, eol)
+            .map_err(|stop| self.unclosed_details = self.ix..stop + 1)
+            .is_ok()
+    }
+    // `math_end` for `$` math, unless an earlier scan already failed there
+    fn inline_math_end(&mut self, input: &[u8]) -> Option<usize> {
+        if self.unclosed_math.contains(&self.ix) {
+            return None;
+        }
+        math_end(input, self.ix, false)
+            .map_err(|stop| self.unclosed_math = self.ix..stop + 1)
+            .ok()
+    }
+    // the math from `self.ix` to `end`, delimiters included; anything that isn't valid is
+    // written as is, and reported
+    fn write_math<O: Write>(
+        &mut self,
+        input: &[u8],
+        end: usize,
+        display: bool,
+        output: &mut O,
+    ) -> SamupResult {
+        let delimiter = if display { 2 } else { 1 };
+        let tex = String::from_utf8_lossy(&input[self.ix + delimiter..=end - delimiter]);
+        match math::to_mathml(&tex, display) {
+            Ok(mathml) => output.write_all(mathml.as_bytes()),
+            Err(message) => {
+                self.diagnose(input, message);
+                write_escaped(output, &input[self.ix..=end])
+            }
+        }
+    }
+    fn transcribe_newline<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        // \r\n is a single line break
+        if input[self.ix] == b'\r' && input.get(self.ix + 1) == Some(&b'\n') {
+            return Ok(Some(self.prev_c));
+        }
+        if let Some(Tag::FootNoteRef(_)) = self.block() {
+            self.close_block(output)?;
+        }
+        let line = &input[..self.ix];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.hard_break = matches!(self.leaf(), Some(Tag::P | Tag::Dd))
+            && (self.prev_c == C::Backslash
+                || self.prev_c == C::Whitespace && line.ends_with(b"  "));
+        // blank line ends the paragraph (or table), and any quotes
+        if self.prev_c == C::Newline {
+            self.close_quotes(0, output)?;
+            if self.leaf().is_some() {
+                self.close_block(output)?;
+            }
+        }
+        Ok(None)
+    }
+    // _i_, *strong*, ~~del~~, ++u++, ^sup^, ~sub~
+    fn transcribe_emphasis<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let delim = input[self.ix];
+        let mut end = self.ix;
+        // __ -> _, ** -> *
+        while input.get(end + 1) == Some(&delim) {
+            end += 1;
+        }
+        let next = input.get(end + 1).copied();
+        let (closes, opens) = match emphasis_tag(delim, end + 1 - self.ix) {
+            // H~2~O, x^2^: may open and close inside a word, but can't span whitespace
+            Some(tag @ (Tag::Sup | Tag::Sub)) => {
+                let closes = self.innermost_inline() == Some(&tag);
+                let opens = !closes && self.script_closes(input, delim, end + 1);
+                (closes, opens)
+            }
+            Some(tag) => {
+                let prev = self.ix.checked_sub(1).map(|ix| input[ix]);
+                let (can_open, can_close) = flanking(prev, next);
+                let closes = can_close
+                    && (self.reopen.contains(&tag) || self.inline_position(&tag).is_some());
+                (closes, !closes && can_open)
+            }
+            None => (false, false),
+        };
+        let tag = emphasis_tag(delim, end + 1 - self.ix).unwrap_or(Tag::I);
+        let next_c = if closes {
+            self.close_inline(&tag, output)?;
+            None
+        } else if opens && !self.inline_too_deeply(input) {
+            self.ensure_block(output)?;
+            tag.write_open(output)?;
+            self.push_tag(tag);
+            None
+        } else {
+            self.ensure_block(output)?;
+            output.write_all(&input[self.ix..=end])?;
+            Some(C::Content)
+        };
+        self.ix = end;
         Ok(next_c)
     }
-    fn transcribe_paren<O: Write>(&mut self, output: &mut O) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace | C::Content => (),
-            C::Newline => (),
-            C::SqBracketL => {
-                if self.stack_empty() {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-                output.write_all(b"[")?;
+    // closes `tag` and everything opened inside it, then reopens the latter once there's
+    // something to put in them: `*a _b* c_` -> `<strong>a <i>b</i></strong><i> c</i>`
+    fn close_inline<O: Write>(&mut self, tag: &Tag, output: &mut O) -> SamupResult {
+        // still empty, so never written
+        if let Some(pos) = self.reopen.iter().rposition(|t| t == tag) {
+            self.reopen.remove(pos);
+            return Ok(());
+        }
+        let Some(pos) = self.inline_position(tag) else {
+            return Ok(());
+        };
+        let inner: Vec<Tag> = self.tag_stack.drain(..pos).collect();
+        self.inline_depth -= pos;
+        for t in &inner {
+            t.write_close(output)?;
+        }
+        if let Some(tag) = self.pop_tag() {
+            tag.write_close(output)?;
+        }
+        // anything already waiting to be reopened was inside these
+        let reopen = std::mem::take(&mut self.reopen);
+        self.reopen = inner.into_iter().rev().chain(reopen).collect();
+        Ok(())
+    }
+    fn transcribe_sq_bracket_l<O: Write>(
+        &mut self,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        self.ensure_block(output)?;
+        // no links inside link labels, footnote links included
+        let in_link = self.in_link();
+        // [^1]
+        if let Some((n, end)) = foot_note_ix(input, self.ix) {
+            let Some(n) = n else {
+                self.diagnose(input, String::from("footnote number too large"));
+                write_escaped(output, &input[self.ix..=end])?;
+                self.ix = end;
+                return Ok(Some(C::SqBracketR));
+            };
+            if in_link {
+                write!(output, "<sup>{}</sup>", n.ix())?;
+            } else {
+                let tag = Tag::FootNoteLink(n);
+                tag.write_open(output)?;
+                tag.write_close(output)?;
             }
-            C::ParenL => {
-                output.write_all(b"(")?;
+            self.ix = end;
+            return Ok(Some(C::SqBracketR));
+        }
+        if in_link {
+            output.write_all(b"[")?;
+            return Ok(Some(C::Content));
+        }
+        // [label][ref]
+        if let Some((label_end, end)) = ref_link_end(input, self.ix) {
+            let name = match &input[label_end + 2..end] {
+                // [label][]
+                b"" => &input[self.ix + 1..label_end],
+                name => name,
+            };
+            let Some(url) = self.definitions.get(&refs::normalize(name)).cloned() else {
+                let name = String::from_utf8_lossy(name).into_owned();
+                self.diagnose(input, format!("undefined link reference [{name}]"));
+                write_escaped(output, &input[self.ix..=end])?;
+                self.ix = end;
+                return Ok(Some(C::Content));
+            };
+            let label = self.transcribe_inline_with(input, &input[self.ix + 1..label_end], true)?;
+            let mut link = self.new_link(LinkState::Label, url);
+            let mut end = end;
+            if let Some((attrs, attrs_end)) = link_attributes(input, end + 1) {
+                link.attrs = attrs;
+                end = attrs_end;
             }
-            C::ParenR => {
-                output.write_all(b")")?;
+            if self.allow_link(input, &link.url) {
+                let tag = Tag::Link(link);
+                tag.write_open(output)?;
+                output.write_all(&label)?;
+                tag.write_close(output)?;
+            } else if self.options.disallowed_links == DisallowedLinks::Text {
+                output.write_all(&label)?;
             }
-            C::SqBracketR => match self.pop_tag() {
-                Some(
-                    mut tag @ Tag::Link(InnerLink {
-                        state: LinkState::Link,
-                        ..
-                    }),
-                ) => {
-                    tag.write_open(output)?;
-                    tag.end_url();
-                    self.push_tag(tag);
-                }
-                Some(tag @ Tag::FootNoteLink(_)) => {
-                    tag.write_open(output)?;
-                }
-                Some(tag) => {
-                    output.write_all(b"]")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                    output.write_all(b"]")?;
-                }
-            },
-            C::Underscore => {
-                Tag::I.write_open(output)?;
-                self.push_tag(Tag::I);
+            self.ix = end;
+            return Ok(Some(C::SqBracketR));
+        }
+        let start = self.ix + 1;
+        let Some(end) = self.link_end(input, start) else {
+            output.write_all(b"[")?;
+            return Ok(Some(C::Content));
+        };
+        let url = String::from_utf8_lossy(&input[start..end]).into_owned();
+        // [url](label){...}
+        if input.get(end + 1) == Some(&b'(')
+            && let Some(label_end) = self.label_end(input, end + 2)
+        {
+            let mut link = self.new_link(LinkState::Label, url);
+            // skipped over by `transcribe_paren_r`
+            let attrs = link_attributes(input, label_end + 1);
+            if !self.allow_link(input, &link.url) {
+                if self.options.disallowed_links == DisallowedLinks::Drop {
+                    self.ix = attrs.map_or(label_end, |(_, end)| end);
+                    return Ok(Some(C::ParenR));
+                }
+                link.state = LinkState::Disallowed;
             }
-            C::Asterisk => {
-                Tag::Strong.write_open(output)?;
-                self.push_tag(Tag::Strong);
+            if let Some((attrs, _)) = attrs {
+                link.attrs = attrs;
             }
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b"#")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"#")?,
-            },
-            C::Caret => output.write_all(b"[^")?,
-            C::Colon => match self.pop_tag() {
-                Some(tag @ Tag::FootNoteRef(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b":")?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(b":")?;
-                }
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let n = n.ix();
-                    output.write_fmt(format_args!("[^{n}"))?;
-                }
-                // unreachable?
-                Some(tag) => self.push_tag(tag),
-                // unreachable?
-                None => (),
-            },
+            let tag = Tag::Link(link);
+            tag.write_open(output)?;
+            self.push_tag(tag);
+            self.ix = end + 1;
+            return Ok(Some(C::ParenL));
+        }
+        // [url]{...}
+        let mut link = self.new_link(LinkState::Link, url);
+        if self.allow_link(input, &link.url) {
+            if let Some((attrs, _)) = link_attributes(input, end + 1) {
+                link.attrs = attrs;
+            }
+            Tag::Link(link).write_link_no_title(output)?;
+        } else if self.options.disallowed_links == DisallowedLinks::Text {
+            write_escaped(output, &input[start..end])?;
+        }
+        self.ix = link_attributes(input, end + 1).map_or(end, |(_, end)| end);
+        Ok(Some(C::SqBracketR))
+    }
+    // ![src](alt){width=100 height=50}
+    fn transcribe_bang<O: Write>(
+        &mut self,
+        curr_char: u8,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let start = self.ix + 2;
+        let Some(mut end) = (input.get(self.ix + 1) == Some(&b'['))
+            .then(|| self.link_end(input, start))
+            .flatten()
+        else {
+            return self.transcribe_content(curr_char, output);
         };
-        Ok(None)
+        self.ensure_block(output)?;
+        // rewritten and allowlisted like any link
+        let src = self.rewrite_url(String::from_utf8_lossy(&input[start..end]).into_owned());
+        let mut alt = String::new();
+        if input.get(end + 1) == Some(&b'(')
+            && let Some(label_end) = self.label_end(input, end + 2)
+        {
+            alt = String::from_utf8_lossy(&input[end + 2..label_end]).into_owned();
+            end = label_end;
+        }
+        if !self.allow_link(input, &src) {
+            if self.options.disallowed_links == DisallowedLinks::Text {
+                write_escaped(output, alt.as_bytes())?;
+            }
+            self.ix = attributes(input, end + 1).map_or(end, |(_, end)| end);
+            return Ok(Some(C::ParenR));
+        }
+        let mut image = InnerImage {
+            src,
+            alt,
+            width: None,
+            height: None,
+        };
+        if let Some((attrs, attrs_end)) = attributes(input, end + 1)
+            && let Some((width, height)) = image_size(&attrs)
+        {
+            image.width = width;
+            image.height = height;
+            end = attrs_end;
+        }
+        Tag::Image(image).write_open(output)?;
+        self.ix = end;
+        Ok(Some(C::ParenR))
     }
-    fn transcribe_digit<O: Write>(
+    fn transcribe_paren_r<O: Write>(
         &mut self,
         curr_char: u8,
+        input: &[u8],
         output: &mut O,
     ) -> SamupResult<Option<C>> {
-        if self.prev_c == C::Caret {
-            match self.pop_tag() {
-                // no nested footnotes
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let ix = n.ix();
-                    let c = crate::char_to_digit(curr_char);
-                    output.write_fmt(format_args!("[^{ix}{c}"))?
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                    self.push_tag(Tag::new_fn_link(curr_char));
-                    return Ok(None);
-                }
-                None => {
-                    self.push_tag(Tag::new_fn_link(curr_char));
-                    return Ok(None);
-                }
+        if !self.link_open() {
+            return self.transcribe_content(curr_char, output);
+        }
+        // close anything left open inside the label along with it
+        self.reopen.clear();
+        while let Some(tag) = self.pop_tag() {
+            tag.write_close(output)?;
+            if let Tag::Link(_) = tag {
+                break;
             }
-        } else {
-            match self.pop_tag() {
-                Some(mut tag @ Tag::FootNoteLink(_)) | Some(mut tag @ Tag::FootNoteRef(_)) => {
-                    tag.push_fn_digit(curr_char);
-                    self.push_tag(tag);
-                    return Ok(None);
-                }
-                Some(mut tag @ Tag::Link(_)) => {
-                    tag.push_link(str::from_utf8(&[curr_char]).unwrap());
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(&[curr_char])?;
-                    self.push_tag(tag);
-                }
-                None => {
-                    output.write_all(&[curr_char])?;
-                }
-            };
         }
-        Ok(Some(C::Content))
+        // already written by `transcribe_sq_bracket_l`
+        if let Some((_, end)) = link_attributes(input, self.ix + 1) {
+            self.ix = end;
+        }
+        Ok(None)
     }
     fn transcribe_content<O: Write>(
         &mut self,
         curr_char: u8,
         output: &mut O,
     ) -> SamupResult<Option<C>> {
-        match self.prev_c {
-            C::Whitespace | C::Content => match self.pop_tag() {
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-                Some(
-                    mut tag @ Tag::Link(InnerLink {
-                        state: LinkState::Link,
-                        ..
-                    }),
-                ) => {
-                    tag.push_link(str::from_utf8(&[curr_char]).unwrap());
-                    self.push_tag(tag);
-                    return Ok(None);
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-            },
-            C::Newline => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_close(output)?;
-                    output.write_all(b"\n")?;
-                }
-                Some(tag) => {
-                    output.write_all(b"\n")?;
-                    self.push_tag(tag)
-                }
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-            },
-            C::Underscore => match self.pop_tag() {
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-            },
-            C::Asterisk => match self.pop_tag() {
-                None => {
-                    Tag::P.write_open(output)?;
-                    self.push_tag(Tag::P);
-                }
-                Some(tag) => {
-                    self.push_tag(tag);
-                }
-            },
-            C::Octothorpe => match self.pop_tag() {
-                Some(tag @ Tag::H(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b"#")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"#")?,
-            },
-            C::Caret => output.write_all(b"^")?,
-            C::Colon => output.write_all(b":")?,
-            C::SqBracketL => {
-                match self.pop_tag() {
-                    Some(Tag::Link(s)) => {
-                        output.write_fmt(format_args!("[{s}"))?;
-                    }
-                    Some(tag) => self.push_tag(tag),
-                    None => {}
-                };
-                self.push_tag(Tag::new_link(curr_char));
-                return Ok(None);
+        self.ensure_block(output)?;
+        write_escaped(output, &[curr_char])?;
+        Ok(None)
+    }
+    // `:shortcode:` as its emoji, if `Options::emoji` is set and it's a known one
+    fn transcribe_colon<O: Write>(
+        &mut self,
+        curr_char: u8,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        if self.options.emoji
+            && let Some(end) = shortcode_end(input, self.ix)
+            && let Ok(shortcode) = std::str::from_utf8(&input[self.ix + 1..end])
+            && let Some(emoji) = emoji::emoji(shortcode)
+        {
+            self.ensure_block(output)?;
+            output.write_all(emoji.as_bytes())?;
+            self.ix = end;
+            return Ok(Some(C::Content));
+        }
+        self.transcribe_content(curr_char, output)
+    }
+    // https://...
+    fn transcribe_autolink<O: Write>(
+        &mut self,
+        curr_char: u8,
+        input: &[u8],
+        output: &mut O,
+    ) -> SamupResult<Option<C>> {
+        let end = (self.options.autolink
+            && !(self.ix > 0 && input[self.ix - 1].is_ascii_alphanumeric())
+            && !self.in_link())
+        .then(|| autolink_end(input, self.ix, &self.options.autolink_schemes))
+        .flatten();
+        let Some(end) = end else {
+            return self.transcribe_content(curr_char, output);
+        };
+        self.ensure_block(output)?;
+        let url = String::from_utf8_lossy(&input[self.ix..=end]).into_owned();
+        let link = self.new_link(LinkState::Link, url);
+        if self.allow_link(input, &link.url) {
+            Tag::Link(link).write_link_no_title(output)?;
+        } else if self.options.disallowed_links == DisallowedLinks::Text {
+            write_escaped(output, &input[self.ix..=end])?;
+        }
+        self.ix = end;
+        Ok(Some(C::Content))
+    }
+    fn open_block<O: Write>(&mut self, tag: Tag, output: &mut O) -> SamupResult {
+        self.write_indent(output)?;
+        tag.write_open(output)?;
+        if tag.is_multiline() {
+            self.write_newline(output)?;
+        }
+        self.push_tag(tag);
+        Ok(())
+    }
+    // closes the innermost block along with any inline tags still open inside it
+    fn close_block<O: Write>(&mut self, output: &mut O) -> SamupResult {
+        self.reopen.clear();
+        while let Some(tag) = self.pop_tag() {
+            if let Tag::Table(InnerTable { body: true, .. }) = tag {
+                self.indent(self.depth() + 1, output)?;
+                output.write_all(b"</tbody>")?;
+                self.write_newline(output)?;
+            }
+            if tag.is_multiline() {
+                self.write_indent(output)?;
+            }
+            tag.write_close(output)?;
+            if tag.is_block() {
+                return self.write_newline(output);
             }
-            C::SqBracketR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) | Some(tag @ Tag::FootNoteLink(_)) => {
-                    tag.write_open(output)?;
-                    self.push_tag(tag);
-                }
-                Some(Tag::FootNoteRef(n)) => {
-                    let ix = n.ix();
-                    output.write_fmt(format_args!("[^{ix}]"))?;
-                }
-                Some(tag) => self.push_tag(tag),
-                None => {
-                    if self.stack_empty() {
-                        Tag::P.write_open(output)?;
-                        self.push_tag(Tag::P);
-                    }
-                }
-            },
-            C::ParenL => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    self.push_tag(tag);
-                }
-                Some(tag) => {
-                    output.write_all(b"(")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b"(")?,
-            },
-            C::ParenR => match self.pop_tag() {
-                Some(tag @ Tag::Link(_)) => {
-                    tag.write_close(output)?;
-                }
-                Some(tag) => {
-                    output.write_all(b")")?;
-                    self.push_tag(tag);
-                }
-                None => output.write_all(b")")?,
-            },
-            C::Digit => match self.pop_tag() {
-                Some(Tag::FootNoteLink(n)) | Some(Tag::FootNoteRef(n)) => {
-                    let ix = n.ix();
-                    output.write_fmt(format_args!("[^{ix}"))?;
-                }
-                // shouldn't happen
-                Some(tag) => self.push_tag(tag),
-                None => (),
-            },
         }
-        output.write_all(&[curr_char])?;
-        Ok(None)
+        Ok(())
+    }
+    // closes blocks until at most `quotes` blockquotes are open
+    fn close_quotes<O: Write>(&mut self, quotes: usize, output: &mut O) -> SamupResult {
+        while self.quote_depth() > quotes {
+            self.close_block(output)?;
+        }
+        Ok(())
+    }
+    fn ensure_block<O: Write>(&mut self, output: &mut O) -> SamupResult {
+        if !self.inline && self.leaf().is_none() {
+            self.open_block(Tag::P, output)?;
+        }
+        for tag in std::mem::take(&mut self.reopen) {
+            tag.write_open(output)?;
+            self.push_tag(tag);
+        }
+        Ok(())
+    }
+    fn write_indent<O: Write>(&self, output: &mut O) -> SamupResult {
+        self.indent(self.depth(), output)
+    }
+    fn indent<O: Write>(&self, depth: usize, output: &mut O) -> SamupResult {
+        if self.options.output_mode == OutputMode::Pretty {
+            output.write_all("  ".repeat(depth).as_bytes())?;
+        }
+        Ok(())
+    }
+    // number of enclosing blocks
+    fn depth(&self) -> usize {
+        self.tag_stack.iter().filter(|tag| tag.is_block()).count()
+    }
+    fn write_newline<O: Write>(&self, output: &mut O) -> SamupResult {
+        if self.options.output_mode == OutputMode::Pretty {
+            output.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+    fn block(&self) -> Option<&Tag> {
+        self.tag_stack.get(self.inline_depth)
+    }
+    // the innermost block, unless it's a container
+    fn leaf(&self) -> Option<&Tag> {
+        self.block().filter(|tag| !tag.is_container())
+    }
+    // whether another container would go past `MAX_NESTING`, reporting it if so
+    fn nested_too_deeply(&mut self, input: &[u8]) -> bool {
+        let containers = self.tag_stack.iter().filter(|tag| tag.is_container());
+        let too_deep = containers.count() >= MAX_NESTING;
+        if too_deep {
+            self.diagnose(input, String::from("blocks nested too deeply"));
+        }
+        too_deep
+    }
+    fn quote_depth(&self) -> usize {
+        self.tag_stack
+            .iter()
+            .filter(|tag| **tag == Tag::Blockquote)
+            .count()
+    }
+    // whether another inline tag would go past `MAX_INLINE_NESTING`, reporting it if so
+    fn inline_too_deeply(&mut self, input: &[u8]) -> bool {
+        let too_deep = self.inline_depth + self.reopen.len() >= MAX_INLINE_NESTING;
+        if too_deep {
+            self.diagnose(input, String::from("inline markup nested too deeply"));
+        }
+        too_deep
+    }
+    // the innermost inline tag, counting any waiting to be reopened
+    fn innermost_inline(&self) -> Option<&Tag> {
+        self.reopen.last().or_else(|| self.inline_tags().next())
+    }
+    // depth of `tag` among the inline tags opened since the innermost block or link
+    fn inline_position(&self, tag: &Tag) -> Option<usize> {
+        self.inline_tags()
+            .take_while(|t| !matches!(t, Tag::Link(_)))
+            .position(|t| t == tag)
+    }
+    // the inline tags opened since the innermost block, innermost first
+    fn inline_tags(&self) -> impl Iterator<Item = &Tag> {
+        self.tag_stack.iter().take(self.inline_depth)
+    }
+    // whether the innermost container is a `<dl>`
+    fn in_dl(&self) -> bool {
+        matches!(
+            self.tag_stack.iter().find(|tag| tag.is_container()),
+            Some(Tag::Dl)
+        )
+    }
+    // whether the innermost container is a task list
+    fn in_task_list(&self) -> bool {
+        matches!(
+            self.tag_stack.iter().find(|tag| tag.is_container()),
+            Some(Tag::Ul)
+        )
+    }
+    // whether this is inside a link's label, either one opened here or one being transcribed
+    // on its own
+    fn in_link(&self) -> bool {
+        self.link_label || self.link_open()
+    }
+    fn link_open(&self) -> bool {
+        self.inline_tags().any(|tag| matches!(tag, Tag::Link(_)))
+    }
+    // whether the word starting at `start` has a lone `delim` (not part of a `~~`) after its
+    // first character, remembering the answer for the rest of the word up to it
+    fn script_closes(&mut self, input: &[u8], delim: u8, start: usize) -> bool {
+        let (cached, starts, closes) = &self.script_closer;
+        if *cached == delim && starts.contains(&start) {
+            return *closes;
+        }
+        let mut ix = start;
+        let closes = loop {
+            match input.get(ix) {
+                Some(c) if is_space(*c) => break false,
+                None => break false,
+                Some(c)
+                    if ix > start
+                        && *c == delim
+                        && input[ix - 1] != delim
+                        && input.get(ix + 1) != Some(&delim) =>
+                {
+                    break true;
+                }
+                _ => ix += 1,
+            }
+        };
+        // a closer only answers for starts before it, while the end of the word answers for
+        // every start up to it
+        let starts = if closes { start..ix } else { start..ix + 1 };
+        self.script_closer = (delim, starts, closes);
+        closes
+    }
+    // `link_end`, unless an earlier scan already failed there
+    fn link_end(&mut self, input: &[u8], start: usize) -> Option<usize> {
+        if self.unclosed_url.contains(&start) {
+            return None;
+        }
+        link_end(input, start)
+            .map_err(|stop| self.unclosed_url = start..stop + 1)
+            .ok()
+    }
+    // `label_end`, unless an earlier scan already failed there
+    fn label_end(&mut self, input: &[u8], start: usize) -> Option<usize> {
+        if self.unclosed_label.contains(&start) {
+            return None;
+        }
+        label_end(input, start)
+            .map_err(|stop| self.unclosed_label = start..stop + 1)
+            .ok()
     }
     fn push_tag(&mut self, tag: Tag) {
+        self.inline_depth = if tag.is_block() {
+            0
+        } else {
+            self.inline_depth + 1
+        };
         self.tag_stack.push_front(tag);
     }
     fn pop_tag(&mut self) -> Option<Tag> {
-        self.tag_stack.pop_front()
+        let tag = self.tag_stack.pop_front()?;
+        self.inline_depth = if tag.is_block() {
+            self.tag_stack.iter().take_while(|t| !t.is_block()).count()
+        } else {
+            self.inline_depth - 1
+        };
+        Some(tag)
     }
     fn stack_empty(&self) -> bool {
         self.tag_stack.front().is_none()
@@ -1323,3 +1489,473 @@ impl Default for Transcriber {
         Self::new()
     }
 }
+
+fn emphasis_tag(delim: u8, run: usize) -> Option<Tag> {
+    match (delim, run) {
+        (b'_', _) => Some(Tag::I),
+        (b'*', _) => Some(Tag::Strong),
+        (b'~', 1) => Some(Tag::Sub),
+        (b'~', _) => Some(Tag::Del),
+        (b'+', 2) => Some(Tag::U),
+        (b'^', 1) => Some(Tag::Sup),
+        _ => None,
+    }
+}
+
+// whether a delimiter run between `prev` and `next` (`None` at either end of the input) can
+// open and/or close: it has to be left-flanking to open and right-flanking to close, and
+// one that's both (inside a word, like `snake_case`) only counts next to punctuation
+fn flanking(prev: Option<u8>, next: Option<u8>) -> (bool, bool) {
+    let space = |c: Option<u8>| c.is_none_or(is_space);
+    let punct = |c: Option<u8>| c.is_some_and(|c| c.is_ascii_punctuation());
+    let left = !space(next) && (!punct(next) || space(prev) || punct(prev));
+    let right = !space(prev) && (!punct(prev) || space(next) || punct(next));
+    (
+        left && (!right || punct(prev)),
+        right && (!left || punct(next)),
+    )
+}
+
+// index of the last byte of a bare url starting at `ix` with one of `schemes`, minus any
+// trailing punctuation and unbalanced closing parens or brackets
+fn autolink_end(input: &[u8], ix: usize, schemes: &[String]) -> Option<usize> {
+    let rest = &input[ix..];
+    let scheme = schemes.iter().find(|scheme| {
+        rest.len() >= scheme.len() && rest[..scheme.len()].eq_ignore_ascii_case(scheme.as_bytes())
+    })?;
+    let mut url = &rest[..rest
+        .iter()
+        .take_while(|c| !is_space(**c) && !matches!(c, b'<' | b'>'))
+        .count()];
+    // closing parens and brackets past the opening ones, counted once up front
+    let count = |c: u8| url.iter().filter(|b| **b == c).count();
+    let mut parens = count(b')').saturating_sub(count(b'('));
+    let mut brackets = count(b']').saturating_sub(count(b'['));
+    loop {
+        url = match url.last() {
+            Some(b'.' | b',' | b':' | b';' | b'!' | b'?' | b'\'' | b'"' | b'*' | b'_' | b'~') => {
+                &url[..url.len() - 1]
+            }
+            Some(b')') if parens > 0 => {
+                parens -= 1;
+                &url[..url.len() - 1]
+            }
+            Some(b']') if brackets > 0 => {
+                brackets -= 1;
+                &url[..url.len() - 1]
+            }
+            _ => break,
+        };
+    }
+    (url.len() > scheme.len()).then(|| ix + url.len() - 1)
+}
+
+// `: ` at the start of a definition; returns its length
+fn definition_marker(line: &[u8]) -> Option<usize> {
+    let rest = line.strip_prefix(b":")?;
+    let len = rest
+        .iter()
+        .take_while(|c| C::from(**c) == C::Whitespace)
+        .count();
+    (len > 0 && len < rest.len()).then_some(1 + len)
+}
+
+// `!!! type title`; returns the type and the title, which may be empty
+fn callout(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = line.strip_prefix(CALLOUT_FENCE)?;
+    if !rest.first().is_some_and(|c| C::from(*c) == C::Whitespace) {
+        return None;
+    }
+    let rest = rest.trim_ascii();
+    let len = rest
+        .iter()
+        .take_while(|c| c.is_ascii_alphanumeric() || **c == b'-' || **c == b'_')
+        .count();
+    if len == 0 || rest.get(len).is_some_and(|c| C::from(*c) != C::Whitespace) {
+        return None;
+    }
+    Some((&rest[..len], rest[len..].trim_ascii_start()))
+}
+
+// `+++ summary` or `+++`; returns the summary, which may be empty
+fn details(line: &[u8]) -> Option<&[u8]> {
+    let rest = line.strip_prefix(DETAILS_FENCE)?;
+    if rest.first().is_some_and(|c| C::from(*c) != C::Whitespace) {
+        return None;
+    }
+    Some(rest.trim_ascii())
+}
+
+// the index of the `$` ending the math starting with the `$` (or `$$`) at `ix`, or else of
+// where the scan stopped, which no `$` math starting before it can get past either; `$` math
+// can't start with whitespace, end with whitespace, be followed by a digit, end at a `$`
+// next to another one or go on over several lines, so `$5 and $10` (even with `$$x$$`
+// after it) isn't math, while `$$` math may go on until a blank line
+fn math_end(input: &[u8], ix: usize, display: bool) -> Result<usize, usize> {
+    let start = if display { ix + 2 } else { ix + 1 };
+    if !display && input.get(start).is_none_or(|c| is_space(*c)) {
+        return Err(start);
+    }
+    let mut end = start;
+    while end < input.len() {
+        match &input[end..] {
+            [b'\\', _, ..] => end += 1,
+            [b'$', b'$', ..] if display && end > start => return Ok(end + 1),
+            [b'$', rest @ ..]
+                if !display
+                    && end > start
+                    && !is_space(input[end - 1])
+                    && input[end - 1] != b'$'
+                    && !rest
+                        .first()
+                        .is_some_and(|c| c.is_ascii_digit() || *c == b'$') =>
+            {
+                return Ok(end);
+            }
+            [b'\n', ..] if !display => return Err(end),
+            [b'\n', rest @ ..] if rest.trim_ascii_start().starts_with(b"\n") => return Err(end),
+            _ => (),
+        }
+        end += 1;
+    }
+    Err(input.len())
+}
+
+// the index of the `:` ending the `:shortcode:` starting at `ix`; a shortcode can't be part
+// of a longer word, so `a:b:c` and `12:30:45` aren't any
+fn shortcode_end(input: &[u8], ix: usize) -> Option<usize> {
+    if ix > 0 && input[ix - 1].is_ascii_alphanumeric() {
+        return None;
+    }
+    let len = input[ix + 1..]
+        .iter()
+        .take_while(|c| emoji::is_shortcode_char(**c))
+        .count();
+    let end = ix + 1 + len;
+    (len > 0
+        && input.get(end) == Some(&b':')
+        && !input.get(end + 1).is_some_and(u8::is_ascii_alphanumeric))
+    .then_some(end)
+}
+
+fn is_space(c: u8) -> bool {
+    matches!(C::from(c), C::Whitespace | C::Newline)
+}
+
+// whether `line` (sans quote markers) starts a block other than a paragraph
+fn starts_block(line: &[u8]) -> bool {
+    heading_level(line, 0).is_some()
+        || is_thematic_break(line)
+        || toc::is_toc_directive(line)
+        || tasks::task_marker(line).is_some()
+        || foot_note_ix(line, 0)
+            .is_some_and(|(n, end)| n.is_some() && line.get(end + 1) == Some(&b':'))
+        || refs::definition(line).is_some()
+        || line.trim_ascii_end() == RAW_OPEN
+        || line.trim_ascii_end() == CALLOUT_FENCE
+        || callout(line).is_some()
+        || details(line).is_some()
+        || line.starts_with(b"$$")
+}
+
+// `{key=value key="a value" key}` starting at `{`; returns the pairs and the index of `}`
+fn attributes(input: &[u8], ix: usize) -> Option<(Vec<(String, String)>, usize)> {
+    if input.get(ix) != Some(&b'{') {
+        return None;
+    }
+    let is_key = |c: &u8| c.is_ascii_alphanumeric() || *c == b'-' || *c == b'_';
+    let mut attrs = Vec::new();
+    let mut ix = ix + 1;
+    loop {
+        while input.get(ix).is_some_and(|c| C::from(*c) == C::Whitespace) {
+            ix += 1;
+        }
+        if *input.get(ix)? == b'}' {
+            return Some((attrs, ix));
+        }
+        let key_len = input[ix..].iter().take_while(|c| is_key(c)).count();
+        if key_len == 0 {
+            return None;
+        }
+        let key = String::from_utf8_lossy(&input[ix..ix + key_len]).into_owned();
+        ix += key_len;
+        let mut value = String::new();
+        if input.get(ix) == Some(&b'=') {
+            ix += 1;
+            if input.get(ix) == Some(&b'"') {
+                let len = input[ix + 1..]
+                    .iter()
+                    .take_while(|c| **c != b'"' && C::from(**c) != C::Newline)
+                    .count();
+                // unterminated quote
+                if input.get(ix + 1 + len) != Some(&b'"') {
+                    return None;
+                }
+                value = String::from_utf8_lossy(&input[ix + 1..ix + 1 + len]).into_owned();
+                ix += len + 2;
+            } else {
+                let len = input[ix..]
+                    .iter()
+                    .take_while(|c| **c != b'}' && !is_space(**c))
+                    .count();
+                value = String::from_utf8_lossy(&input[ix..ix + len]).into_owned();
+                ix += len;
+            }
+        }
+        attrs.push((key, value));
+    }
+}
+
+// `{title="..." rel=... target=... download ...}`; anything else isn't for a link
+fn link_attributes(input: &[u8], ix: usize) -> Option<(Vec<(String, String)>, usize)> {
+    // no `id`, which could take over a heading's or footnote's
+    const KEYS: [&str; 7] = [
+        "title", "rel", "target", "download", "hreflang", "type", "class",
+    ];
+    attributes(input, ix).filter(|(attrs, _)| {
+        !attrs.is_empty() && attrs.iter().all(|(key, _)| KEYS.contains(&key.as_str()))
+    })
+}
+
+// `{width=100 height=50}`; anything else isn't an image size
+fn image_size(attrs: &[(String, String)]) -> Option<(Option<u32>, Option<u32>)> {
+    let (mut width, mut height) = (None, None);
+    for (key, value) in attrs {
+        let value = Some(value.parse().ok()?);
+        match key.as_str() {
+            "width" => width = value,
+            "height" => height = value,
+            _ => return None,
+        }
+    }
+    Some((width, height))
+}
+
+// `| a | b |` -> [`a`, `b`]
+fn table_cells(row: &[u8]) -> Vec<&[u8]> {
+    let row = row.trim_ascii();
+    let row = row.strip_prefix(b"|").unwrap_or(row);
+    let row = row.strip_suffix(b"|").unwrap_or(row);
+    row.split(|c| C::from(*c) == C::Pipe)
+        .map(|cell| cell.trim_ascii())
+        .collect()
+}
+
+// `|:--|:-:|--:|` -> [Left, Center, Right]
+fn table_aligns(row: &[u8]) -> Option<Vec<Align>> {
+    if row.first() != Some(&b'|') {
+        return None;
+    }
+    table_cells(row)
+        .into_iter()
+        .map(|cell| {
+            let left = cell.starts_with(b":");
+            let right = cell.ends_with(b":");
+            let dashes =
+                &cell[usize::from(left)..cell.len() - usize::from(right && cell.len() > 1)];
+            if dashes.is_empty() || dashes.iter().any(|c| *c != b'-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (false, false) => Align::None,
+                (true, false) => Align::Left,
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+            })
+        })
+        .collect()
+}
+
+// `---`, `***`, `* * *`, ...
+fn is_thematic_break(line: &[u8]) -> bool {
+    let mut marks = line.iter().filter(|c| C::from(**c) != C::Whitespace);
+    let Some(mark) = marks.next().filter(|c| **c == b'-' || **c == b'*') else {
+        return false;
+    };
+    let mut n = 1;
+    for c in marks {
+        if c != mark {
+            return false;
+        }
+        n += 1;
+    }
+    n >= 3
+}
+
+// number of `>` markers (and leading whitespace) starting the line at `ix`,
+// and the index of the line's content
+fn quote_markers(input: &[u8], mut ix: usize) -> (usize, usize) {
+    let mut quotes = 0;
+    loop {
+        while input.get(ix).is_some_and(|c| C::from(*c) == C::Whitespace) {
+            ix += 1;
+        }
+        if input.get(ix) != Some(&b'>') {
+            return (quotes, ix);
+        }
+        quotes += 1;
+        ix += 1;
+    }
+}
+
+// `#`s starting a heading at `ix`; returns the level and the index of the heading's text
+fn heading_level(input: &[u8], ix: usize) -> Option<(HLevel, usize)> {
+    let octothorpes = input[ix..].iter().take_while(|c| **c == b'#').count();
+    if octothorpes == 0 {
+        return None;
+    }
+    let mut level = HLevel::new();
+    while usize::from(level.level()) < octothorpes && level.inc_level() {}
+    let mut end = ix + usize::from(level.level());
+    // `####### h6` -> <h6># h6</h6>
+    if octothorpes == usize::from(level.level())
+        && input.get(end).is_some_and(|c| C::from(*c) == C::Whitespace)
+    {
+        end += 1;
+    }
+    let eol = line_end(input, end);
+    (!input[end..eol].trim_ascii_end().is_empty()).then_some((level, end))
+}
+
+// `[^12]` starting at `[`; returns the note number (`None` if it's too big for one) and the
+// index of `]`
+fn foot_note_ix(input: &[u8], ix: usize) -> Option<(Option<FootNoteIx>, usize)> {
+    if input.get(ix + 1) != Some(&b'^') {
+        return None;
+    }
+    let first = *input.get(ix + 2).filter(|c| c.is_ascii_digit())?;
+    let mut n = Some(FootNoteIx::new(first));
+    let mut end = ix + 3;
+    while let Some(c) = input.get(end).filter(|c| c.is_ascii_digit()) {
+        n = n.and_then(|mut n| n.push_digit(*c).then_some(n));
+        end += 1;
+    }
+    (input.get(end) == Some(&b']')).then_some((n, end))
+}
+
+// the lines after the one ending at `eol`, without their first `quotes` `>` markers, up to a
+// `fence` line, a line with fewer markers or the end of the input; returns them and the index
+// of the `\n` ending the `fence` line (or the last line before the one with fewer markers)
+fn fenced(input: &[u8], eol: usize, fence: &[u8], quotes: usize) -> (Vec<u8>, usize) {
+    let mut lines = Vec::new();
+    let mut line = (newline_end(input, eol) + 1).min(input.len());
+    while line < input.len() {
+        let Some(start) = skip_quote_markers(input, line, quotes) else {
+            return (lines, line - 1);
+        };
+        let line_eol = line_end(input, start);
+        let next = newline_end(input, line_eol) + 1;
+        if input[start..line_eol].trim_ascii() == fence {
+            return (lines, next - 1);
+        }
+        lines.extend_from_slice(&input[start..next]);
+        line = next;
+    }
+    (lines, input.len() - 1)
+}
+
+// the index just past the first `quotes` `>` markers (and a space after them) of the line at
+// `ix`, or `None` if it has fewer
+fn skip_quote_markers(input: &[u8], mut ix: usize, quotes: usize) -> Option<usize> {
+    if quotes == 0 {
+        return Some(ix);
+    }
+    for _ in 0..quotes {
+        while input.get(ix).is_some_and(|c| C::from(*c) == C::Whitespace) {
+            ix += 1;
+        }
+        if input.get(ix) != Some(&b'>') {
+            return None;
+        }
+        ix += 1;
+    }
+    if input.get(ix) == Some(&b' ') {
+        ix += 1;
+    }
+    Some(ix)
+}
+
+// the start of the `+++` line closing the bare `+++` line ending at `eol`, skipping any
+// `+++ summary` sections in between, or where the scan stopped: the end of the input, or a
+// line quoted less deeply than `quotes`, which ends the blockquote
+fn details_close(input: &[u8], quotes: usize, eol: usize) -> Result<usize, usize> {
+    let mut open = 0;
+    let mut ix = eol;
+    while ix + 1 < input.len() {
+        let (line_quotes, start) = quote_markers(input, newline_end(input, ix) + 1);
+        ix = line_end(input, start);
+        if line_quotes < quotes {
+            return Err(start);
+        }
+        if line_quotes > quotes {
+            continue;
+        }
+        match details(&input[start..ix]) {
+            Some(b"") if open == 0 => return Ok(start),
+            Some(b"") => open -= 1,
+            Some(_) => open += 1,
+            None => (),
+        }
+    }
+    Err(input.len().saturating_sub(1))
+}
+
+// the index of the `\n` ending the line that ends at `eol`, or of the input's last byte
+fn newline_end(input: &[u8], eol: usize) -> usize {
+    match input.get(eol..eol + 2) {
+        Some(b"\r\n") => eol + 1,
+        _ => eol.min(input.len() - 1),
+    }
+}
+
+// index of the newline (or end of input) ending the line that contains `ix`
+fn line_end(input: &[u8], ix: usize) -> usize {
+    ix + input[ix..]
+        .iter()
+        .take_while(|c| C::from(**c) != C::Newline)
+        .count()
+}
+
+// indices of the two `]`s in `[label][ref]` starting at `ix`
+fn ref_link_end(input: &[u8], ix: usize) -> Option<(usize, usize)> {
+    let find = |start: usize| {
+        input[start..]
+            .iter()
+            .position(|c| matches!(c, b'[' | b']' | b'\n'))
+            .map(|len| start + len)
+            .filter(|end| input[*end] == b']')
+    };
+    let label_end = find(ix + 1).filter(|end| *end > ix + 1)?;
+    if input.get(label_end + 1) != Some(&b'[') {
+        return None;
+    }
+    Some((label_end, find(label_end + 2)?))
+}
+
+// index of the `]` closing a link url that starts at `start`, or else of the whitespace (or
+// end of input) that stopped the scan, which no url starting before it can get past either
+fn link_end(input: &[u8], start: usize) -> Result<usize, usize> {
+    let len = input[start..]
+        .iter()
+        .take_while(|c| **c != b']' && !is_space(**c))
+        .count();
+    let end = start + len;
+    match input.get(end) {
+        Some(b']') if len > 0 => Ok(end),
+        _ => Err(end),
+    }
+}
+
+// index of the `)` closing a link label that starts at `start`, or else of the end of the
+// line, which no label starting before it can get past either
+fn label_end(input: &[u8], start: usize) -> Result<usize, usize> {
+    let len = input[start..]
+        .iter()
+        .take_while(|c| **c != b')' && C::from(**c) != C::Newline)
+        .count();
+    let end = start + len;
+    match input.get(end) {
+        Some(b')') => Ok(end),
+        _ => Err(end),
+    }
+}
@@ -1,4 +1,8 @@
-use samup::{SamupResult, transcribe};
+use samup::{
+    Diagnostic, DisallowedLinks, Heading, Options, SamupResult, Task, Transcriber,
+    diagnostics_with, emoji::EMOJI, headings_with, metadata, open_tasks_with, tasks_with, toc_with,
+    transcribe, transcribe_with,
+};
 
 // let s = unsafe { str::from_utf8_unchecked(&output) };
 // println!("test_ actually {s}");
@@ -7,7 +11,18 @@ use samup::{SamupResult, transcribe};
 fn test_content() -> SamupResult {
     let mut output = Vec::new();
     let input = b"a \nb";
-    let expected_out = b"<p>a \nb</p>";
+    let expected_out = b"<p>a \nb</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_out, &o);
+    Ok(())
+}
+
+#[test]
+fn test_hard_break() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"Roses are red\\\nviolets  \r\nare blue\\\n\na\\b \\ c \t d";
+    let expected_out = b"<p>Roses are red<br>\nviolets<br>\nare blue</p>\n<p>a\\b \\ c \t d</p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_out, &o);
@@ -18,7 +33,7 @@ fn test_content() -> SamupResult {
 fn test_paragraph() -> SamupResult {
     let mut output = Vec::new();
     let input = b"abc\n\ndef";
-    let expected_out = b"<p>abc</p>\n<p>def</p>";
+    let expected_out = b"<p>abc</p>\n<p>def</p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_out, &o);
@@ -29,13 +44,13 @@ fn test_paragraph() -> SamupResult {
 fn test_inline() -> SamupResult {
     let mut output = Vec::new();
     let input = b"_italic_ *strong*";
-    let expected_out = b"\n<p><i>italic</i> <strong>strong</strong></p>";
+    let expected_out = b"<p><i>italic</i> <strong>strong</strong></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_out, &o, "terminated");
     output.clear();
     let input = b"_italic";
-    let expected_out = b"\n<p><i>italic</i></p>";
+    let expected_out = b"<p><i>italic</i></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_out, &o, "unterminated");
@@ -46,31 +61,479 @@ fn test_inline() -> SamupResult {
 fn test_h() -> SamupResult {
     let mut output = Vec::new();
     let input = b"# h";
-    let expected_output = b"\n<h1>h</h1>";
+    let expected_output = b"<h1 id=\"h\">h</h1>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "h1");
     output.clear();
     let input = b"####### h6";
-    let expected_output = b"\n<h6># h6</h6>";
+    let expected_output = b"<h6 id=\"h6\"># h6</h6>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "h6#");
     output.clear();
     let input = b"# h1\n## h2";
-    let expected_output = b"\n<h1>h1</h1>\n<h2>h2</h2>";
+    let expected_output = b"<h1 id=\"h1\">h1</h1>\n<h2 id=\"h2\">h2</h2>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "h multiple");
     Ok(())
 }
 
+#[test]
+fn test_h_id() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"# _Hello_, [https://swizzard.pizza](World)!\n# hello world\n# hello world";
+    let expected_output = b"<h1 id=\"hello-world\"><i>Hello</i>, <a href=\"https://swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\">World</a>!</h1>\n<h1 id=\"hello-world-1\">hello world</h1>\n<h1 id=\"hello-world-2\">hello world</h1>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "h id");
+    output.clear();
+    let input = b"# a-1\n# a\n# a\n# a";
+    let expected_output =
+        b"<h1 id=\"a-1\">a-1</h1><h1 id=\"a\">a</h1><h1 id=\"a-2\">a</h1><h1 id=\"a-3\">a</h1>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "h id taken by a literal suffix");
+    output.clear();
+    let input = b"## h2 ";
+    let expected_output: &[u8] =
+        "<h2 id=\"h2\">h2<a class=\"anchor\" href=\"#h2\">\u{b6}</a></h2>\n".as_ref();
+    transcribe_with(input, &mut output, &Options::new().heading_anchors(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "h anchor");
+    Ok(())
+}
+
+#[test]
+fn test_toc() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"# a\n[[toc]]\n### b\n## c\n# d";
+    let expected_output = b"<h1 id=\"a\">a</h1>\n<nav class=\"toc\">\n  <ol>\n    <li><a href=\"#a\">a</a>\n      <ol>\n        <li><a href=\"#b\">b</a></li>\n        <li><a href=\"#c\">c</a></li>\n      </ol>\n    </li>\n    <li><a href=\"#d\">d</a></li>\n  </ol>\n</nav>\n<h3 id=\"b\">b</h3>\n<h2 id=\"c\">c</h2>\n<h1 id=\"d\">d</h1>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "toc directive");
+    output.clear();
+    let input = b"text\n# a\n## b";
+    let expected_output = b"<nav class=\"toc\"><ol><li><a href=\"#a\">a</a><ol><li><a href=\"#b\">b</a></li></ol></li></ol></nav><p>text</p><h1 id=\"a\">a</h1><h2 id=\"b\">b</h2>";
+    transcribe_with(input, &mut output, &Options::minified().toc(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "toc option");
+    output.clear();
+    let expected_output = b"<nav class=\"toc\"><ol><li><a href=\"#a\">a</a><ol><li><a href=\"#b\">b</a></li></ol></li></ol></nav>";
+    toc_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "toc only");
+    output.clear();
+    let input = b"# a\n> [[toc]]";
+    let expected_output = b"<h1 id=\"a\">a</h1><blockquote><nav class=\"toc\"><ol><li><a href=\"#a\">a</a></li></ol></nav></blockquote>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "quoted toc directive");
+    output.clear();
+    let input = b"# Q&A *<b>*";
+    assert_eq!(
+        headings_with(input, &Options::default())?,
+        vec![Heading {
+            level: 1,
+            id: String::from("q-a-b"),
+            text: String::from("Q&A <b>")
+        }],
+        "heading text"
+    );
+    let expected_output =
+        b"<nav class=\"toc\"><ol><li><a href=\"#q-a-b\">Q&amp;A &lt;b&gt;</a></li></ol></nav>";
+    toc_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "escaped toc");
+    Ok(())
+}
+
+#[test]
+fn test_blockquote() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"> # h\n> _a_ [x](y)\nlazy\n>> nested\n>\n> b\n\nc";
+    let expected_output = b"<blockquote>\n  <h1 id=\"h\">h</h1>\n  <p><i>a</i> <a href=\"x\" target=\"_blank\" rel=\"noopener noreferrer\">y</a>\nlazy</p>\n  <blockquote>\n    <p>nested</p>\n  </blockquote>\n  <p>b</p>\n</blockquote>\n<p>c</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "blockquote");
+    output.clear();
+    let input = b"> a\n# h";
+    let expected_output = b"<blockquote><p>a</p></blockquote><h1 id=\"h\">h</h1>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "blockquote no lazy heading");
+    output.clear();
+    let mut input = ">".repeat(100);
+    input.push_str(" a\n");
+    input.push_str(&"+++ s\n".repeat(100));
+    transcribe_with(input.as_bytes(), &mut output, &Options::minified())?;
+    let o = String::from_utf8_lossy(&output);
+    assert_eq!(
+        o.matches("<blockquote>").count(),
+        64,
+        "blockquotes nested too deeply"
+    );
+    assert_eq!(
+        o.matches("<details>").count(),
+        64,
+        "sections nested too deeply"
+    );
+    let diagnostics = diagnostics_with(input.as_bytes(), &Options::default())?;
+    assert_eq!(diagnostics.len(), 37, "nested too deeply diagnostics");
+    assert_eq!(
+        diagnostics[0],
+        Diagnostic {
+            line: 1,
+            message: String::from("blocks nested too deeply")
+        },
+        "nested too deeply diagnostic"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_definition_list() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"Apple\n: a _red_ fruit\n: a company\nin Cupertino\n\n*Pear*\n: another fruit\n\nafter\n: still a definition\n\nplain\ntext";
+    let expected_output = b"<dl>
+  <dt>Apple</dt>
+  <dd>a <i>red</i> fruit</dd>
+  <dd>a company
+in Cupertino</dd>
+  <dt><strong>Pear</strong></dt>
+  <dd>another fruit</dd>
+  <dt>after</dt>
+  <dd>still a definition</dd>
+</dl>
+<p>plain
+text</p>
+";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "definition list");
+    output.clear();
+
+    let input = b"T\n: d\n# H";
+    let expected_output = b"<dl><dt>T</dt><dd>d</dd></dl><h1 id=\"h\">H</h1>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "minified definition list");
+    output.clear();
+
+    let input = b"a\nb\n: c\n\nd\n: e";
+    let expected_output = b"<p>a\nb\n: c</p><dl><dt>d</dt><dd>e</dd></dl>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "term after a paragraph line");
+    Ok(())
+}
+
+#[test]
+fn test_tasks() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"- [ ] write *docs*\n- [x] ship &\n\n- [X] review\nafter\n-[ ] not\n- [ ]not";
+    let expected_output = b"<ul>
+  <li><input type=\"checkbox\" disabled> write <strong>docs</strong></li>
+  <li><input type=\"checkbox\" checked disabled> ship &amp;</li>
+  <li><input type=\"checkbox\" checked disabled> review</li>
+</ul>
+<p>after
+-[ ] not
+- [ ]not</p>
+";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "tasks");
+    output.clear();
+
+    let input = b"a\n- [ ] b\nT\n: d\n> - [x] c";
+    let expected_output = b"<p>a</p><ul><li><input type=\"checkbox\" disabled> b</li></ul><dl><dt>T</dt><dd>d</dd></dl><blockquote><ul><li><input type=\"checkbox\" checked disabled> c</li></ul></blockquote>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "minified tasks");
+    output.clear();
+
+    let input = b"- [ ] a\n> q\n\n- [ ] b\n\n> r";
+    let expected_output = b"<ul><li><input type=\"checkbox\" disabled> a</li></ul><blockquote><p>q</p></blockquote><ul><li><input type=\"checkbox\" disabled> b</li></ul><blockquote><p>r</p></blockquote>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "tasks before a blockquote");
+
+    let input = b"# Sprint\n- [x] plan\n- [ ] write _docs_ & tests\n\n> - [ ] quoted";
+    let open = vec![
+        Task {
+            line: 3,
+            done: false,
+            text: String::from("write docs & tests"),
+        },
+        Task {
+            line: 5,
+            done: false,
+            text: String::from("quoted"),
+        },
+    ];
+    assert_eq!(
+        open_tasks_with(input, &Options::default())?,
+        open,
+        "open tasks"
+    );
+    assert_eq!(
+        tasks_with(input, &Options::default())?.len(),
+        3,
+        "all tasks"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_callout() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"!!! warning\nMind the *gap*.\n\nSecond\n!!!\n> !!! note Read _this_\n> inside\n> !!!\n!!! tip\n!!!";
+    let expected_output = b"<aside class=\"callout warning\">
+  <p class=\"callout-title\">Warning</p>
+  <p>Mind the <strong>gap</strong>.</p>
+  <p>Second</p>
+</aside>
+<blockquote>
+  <aside class=\"callout note\">
+    <p class=\"callout-title\">Read <i>this</i></p>
+    <p>inside</p>
+  </aside>
+</blockquote>
+<p>!!! tip
+!!!</p>
+";
+    let diagnostics = diagnostics_with(input, &Options::default())?;
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic {
+            line: 9,
+            message: String::from("unknown callout type tip")
+        }],
+        "unknown callout diagnostic"
+    );
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "callout");
+    output.clear();
+
+    let input = b"!!! tip\nT\n: d\n!!! danger\n# H\n!!!\n!!!\ntail";
+    let expected_output = b"<aside class=\"callout tip\"><p class=\"callout-title\">Tip</p><dl><dt>T</dt><dd>d</dd></dl><aside class=\"callout danger\"><p class=\"callout-title\">Danger</p><h1 id=\"h\">H</h1></aside></aside><p>tail</p>";
+    transcribe_with(
+        input,
+        &mut output,
+        &Options::minified().callouts(["tip", "danger"]),
+    )?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "nested configured callouts");
+    Ok(())
+}
+
+#[test]
+fn test_details() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"intro\n+++ Full *log*\nline one\nline two\n\n+++ Inner\n> quoted\n+++\nback\n+++\n+++\nno summary\n+++\nafter";
+    let expected_output = b"<p>intro</p>
+<details>
+  <summary>Full <strong>log</strong></summary>
+  <p>line one
+line two</p>
+  <details>
+    <summary>Inner</summary>
+    <blockquote>
+      <p>quoted</p>
+    </blockquote>
+  </details>
+  <p>back</p>
+</details>
+<details>
+  <summary>Details</summary>
+  <p>no summary</p>
+</details>
+<p>after</p>
+";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "details");
+    output.clear();
+
+    let input = b"!!! note\n+++ x\nin\n!!!\nout";
+    let expected_output = b"<aside class=\"callout note\"><p class=\"callout-title\">Note</p><details><summary>x</summary><p>in</p></details></aside><p>out</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "details closed with callout");
+    output.clear();
+
+    let input = b"intro\n+++\nstray\n\n+++ kept\nin\n+++\nafter";
+    let expected_output =
+        b"<p>intro\n+++\nstray</p><details><summary>kept</summary><p>in</p></details><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "unmatched details fence");
+    Ok(())
+}
+
+#[test]
+fn test_math() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"Let $x_i * y_i$ cost $5 and $10, not \\$3.\n\n$$\n\\sum_{i=1}^n \\frac{a_i}{2} \\leq \\sqrt[3]{\\alpha}\n$$\n\n$\\bar x = \\lim_{n \\to \\infty} \\text{a & b}$ and $a < b$ and $$\\Gamma'$$";
+    let expected_output = "<p>Let <math><msub><mi>x</mi><mi>i</mi></msub><mo>\u{2217}</mo><msub><mi>y</mi><mi>i</mi></msub></math> cost $5 and $10, not $3.</p>
+<math display=\"block\"><munderover><mo>\u{2211}</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mfrac><msub><mi>a</mi><mi>i</mi></msub><mn>2</mn></mfrac><mo>\u{2264}</mo><mroot><mi>\u{3b1}</mi><mn>3</mn></mroot></math>
+<p><math><mover accent=\"true\"><mi>x</mi><mo>\u{af}</mo></mover><mo>=</mo><msub><mi>lim</mi><mrow><mi>n</mi><mo>\u{2192}</mo><mi>\u{221e}</mi></mrow></msub><mtext>a &amp; b</mtext></math> and <math><mi>a</mi><mo>&lt;</mo><mi>b</mi></math> and <math display=\"block\"><mi mathvariant=\"normal\">\u{393}</mi><mo>\u{2032}</mo></math></p>
+".as_bytes();
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "math");
+    output.clear();
+
+    let input = b"$5 and $10 and $$x$$";
+    let expected_output = b"<p>$5 and $10 and <math display=\"block\"><mi>x</mi></math></p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "dollars before display math");
+    output.clear();
+
+    let input = b"$\\nope{x}$ and $\\frac{a$";
+    let expected_output = b"<p>$\\nope{x}$ and $\\frac{a$</p>";
+    let diagnostics = diagnostics_with(input, &Options::default())?;
+    assert_eq!(
+        diagnostics,
+        vec![
+            Diagnostic {
+                line: 1,
+                message: String::from("unknown math command \\nope")
+            },
+            Diagnostic {
+                line: 1,
+                message: String::from("unclosed { in math")
+            }
+        ],
+        "invalid math diagnostics"
+    );
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "invalid math");
+    output.clear();
+
+    let input = b"$a $b $c\n$x$";
+    let expected_output = b"<p>$a $b $c\n<math><mi>x</mi></math></p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "unclosed math");
+    output.clear();
+
+    // deep nesting is an error, not a stack overflow
+    for nested in [
+        "{".repeat(30000),
+        "\\sqrt".repeat(30000),
+        "x^{".repeat(30000),
+    ] {
+        let input = format!("${nested}$");
+        let diagnostics = diagnostics_with(input.as_bytes(), &Options::default())?;
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                line: 1,
+                message: String::from("math nested too deeply")
+            }],
+            "deeply nested math diagnostics"
+        );
+        transcribe_with(input.as_bytes(), &mut output, &Options::minified())?;
+        let expected_output = format!("<p>{input}</p>");
+        let o: &[u8] = output.as_ref();
+        assert_eq!(expected_output.as_bytes(), o, "deeply nested math");
+        output.clear();
+    }
+    let input = format!("${}x{}$", "{".repeat(200), "}".repeat(200));
+    transcribe_with(input.as_bytes(), &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&b"<p><math><mi>x</mi></math></p>", &o, "nested math");
+    output.clear();
+
+    let input = b"$x_1$ and *$y$*";
+    let expected_output = b"<p>$x_1$ and <strong>$y$</strong></p>";
+    transcribe_with(input, &mut output, &Options::minified().math(false))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "no math");
+    Ok(())
+}
+
+#[test]
+fn test_emoji() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"Shipped :tada::rocket: at 12:30:45, :white_check_mark: _done_ :+1:\n:nope: a:tada: :tada:s (:ok:)\n# Hi :wave:";
+    let expected_output = "<p>Shipped \u{1f389}\u{1f680} at 12:30:45, \u{2705} <i>done</i> \u{1f44d}\n:nope: a:tada: :tada:s (\u{1f197})</p><h1 id=\"hi\">Hi \u{1f44b}</h1>".as_bytes();
+    transcribe_with(input, &mut output, &Options::minified().emoji(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "emoji");
+    output.clear();
+
+    let input = b":tada:";
+    let expected_output = b"<p>:tada:</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "emoji off by default");
+
+    assert!(
+        EMOJI.windows(2).all(|w| w[0].0 < w[1].0),
+        "emoji sorted by shortcode"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_hr() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"a\n---\n***\n> * * *\n*strong*\n--";
+    let expected_output = b"<p>a</p>\n<hr>\n<hr>\n<blockquote>\n  <hr>\n</blockquote>\n<p><strong>strong</strong>\n--</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "hr");
+    Ok(())
+}
+
+#[test]
+fn test_table() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"| a | _b_ | c |\n|:--|:-:|--:|\n| [x](y) | *2* | 3[^1] | 4 |\n| 5 |\nafter";
+    let expected_output = b"<table><thead><tr><th style=\"text-align: left\">a</th><th style=\"text-align: center\"><i>b</i></th><th style=\"text-align: right\">c</th></tr></thead><tbody><tr><td style=\"text-align: left\"><a href=\"x\" target=\"_blank\" rel=\"noopener noreferrer\">y</a></td><td style=\"text-align: center\"><strong>2</strong></td><td style=\"text-align: right\">3<a id=\"link-1\" target=\"#ref-1\"><sup>1</sup></a></td></tr><tr><td style=\"text-align: left\">5</td><td style=\"text-align: center\"></td><td style=\"text-align: right\"></td></tr></tbody></table><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "table");
+    output.clear();
+    let input = b"| h |\n|---|\n| x |";
+    let expected_output = b"<table>\n  <thead>\n    <tr>\n      <th>h</th>\n    </tr>\n  </thead>\n  <tbody>\n    <tr>\n      <td>x</td>\n    </tr>\n  </tbody>\n</table>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "table pretty");
+    output.clear();
+    let input = b"| a | b |\n| no separator |";
+    let expected_output = b"<p>| a | b |\n| no separator |</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "not a table");
+    output.clear();
+    let input = b"| a |\n> |---|\n\n> | b |\n|---|";
+    let expected_output = b"<p>| a |</p><blockquote><p>|---|</p></blockquote><blockquote><p>| b |\n|---|</p></blockquote>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "separator quoted differently");
+    output.clear();
+    let input = b"| a |\r\n|---|\r\n| b |\r\nafter";
+    let expected_output = b"<table><thead><tr><th>a</th></tr></thead><tbody><tr><td>b</td></tr></tbody></table><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "crlf table");
+    Ok(())
+}
+
 #[test]
 fn test_link_no_label() -> SamupResult {
     let mut output = Vec::new();
     let input = b"[https://swizzard.pizza]";
     let expected_output =
-        b"<p><a href=\"https://swizzard.pizza\" target=\"_blank\">https://swizzard.pizza</a></p>";
+        b"<p><a href=\"https://swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\">https://swizzard.pizza</a></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "link no label");
@@ -81,10 +544,493 @@ fn test_link_no_label() -> SamupResult {
 fn test_link_label() -> SamupResult {
     let mut output = Vec::new();
     let input = b"[https://swizzard.pizza](my website)";
-    let expected_output = b"<a href=\"https://swizzard.pizza\" target=\"_blank\">my website</a>";
+    let expected_output =
+        b"<p><a href=\"https://swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\">my website</a></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "link label");
+    output.clear();
+    let input = b"[a](b [c] [d](e\n[f](g) [h [i](j) ![k [l](m)";
+    let expected_output = b"<p><a href=\"a\" target=\"_blank\" rel=\"noopener noreferrer\">a</a>(b <a href=\"c\" target=\"_blank\" rel=\"noopener noreferrer\">c</a> <a href=\"d\" target=\"_blank\" rel=\"noopener noreferrer\">d</a>(e\n<a href=\"f\" target=\"_blank\" rel=\"noopener noreferrer\">g</a> [h <a href=\"i\" target=\"_blank\" rel=\"noopener noreferrer\">j</a> ![k <a href=\"l\" target=\"_blank\" rel=\"noopener noreferrer\">m</a></p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "unclosed link labels and urls");
+    Ok(())
+}
+
+#[test]
+fn test_image() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"see ![cat.png](a cat) here!";
+    let expected_output = b"<p>see <img src=\"cat.png\" alt=\"a cat\"> here!</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "image");
+    output.clear();
+    let input = b"![cat.png]{width=100 height=\"50\"} ![cat.png](cat){title=x}";
+    let expected_output = b"<p><img src=\"cat.png\" alt=\"\" width=\"100\" height=\"50\"> <img src=\"cat.png\" alt=\"cat\">{title=x}</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "image size");
+    output.clear();
+    let input = b"[https://swizzard.pizza](![cat.png](cat))";
+    let expected_output = b"<p><a href=\"https://swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\"><img src=\"cat.png\" alt=\"cat\"></a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "image link");
+    Ok(())
+}
+
+#[test]
+fn test_nested_emphasis() -> SamupResult {
+    let mut output = Vec::new();
+    let cases: [(&[u8], &[u8]); 8] = [
+        (b"*_both_*", b"<p><strong><i>both</i></strong></p>\n"),
+        (b"_*both*_", b"<p><i><strong>both</strong></i></p>\n"),
+        (b"_a *b* c_", b"<p><i>a <strong>b</strong> c</i></p>\n"),
+        (b"*a _b_ c*", b"<p><strong>a <i>b</i> c</strong></p>\n"),
+        (
+            b"*a _b* c_",
+            b"<p><strong>a <i>b</i></strong><i> c</i></p>\n",
+        ),
+        (
+            b"_a *b_ c*",
+            b"<p><i>a <strong>b</strong></i><strong> c</strong></p>\n",
+        ),
+        (b"*a _b*", b"<p><strong>a <i>b</i></strong></p>\n"),
+        (b"a * b _ c", b"<p>a * b _ c</p>\n"),
+    ];
+    for (input, expected_output) in cases {
+        transcribe(input, &mut output)?;
+        let o: &[u8] = output.as_ref();
+        assert_eq!(&expected_output, &o, "{}", String::from_utf8_lossy(input));
+        output.clear();
+    }
+    let input = "_a ".repeat(100);
+    transcribe_with(input.as_bytes(), &mut output, &Options::minified())?;
+    let o = String::from_utf8_lossy(&output);
+    assert_eq!(o.matches("<i>").count(), 64, "emphasis nested too deeply");
+    assert_eq!(o.matches("_a").count(), 36, "emphasis nested too deeply");
+    let diagnostics = diagnostics_with(input.as_bytes(), &Options::default())?;
+    assert_eq!(
+        diagnostics.len(),
+        36,
+        "emphasis nested too deeply diagnostics"
+    );
+    assert_eq!(
+        diagnostics[0].to_string(),
+        "line 1: inline markup nested too deeply",
+        "emphasis nested too deeply diagnostic"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_intraword_emphasis() -> SamupResult {
+    let mut output = Vec::new();
+    let cases: [(&[u8], &[u8]); 7] = [
+        (b"snake_case_names", b"<p>snake_case_names</p>\n"),
+        (b"2*3*4 = 24", b"<p>2*3*4 = 24</p>\n"),
+        (b"call my_fn() now", b"<p>call my_fn() now</p>\n"),
+        (b"_foo_bar_", b"<p><i>foo_bar</i></p>\n"),
+        (b"*foo*bar", b"<p><strong>foo*bar</strong></p>\n"),
+        (b"(_a_), *b*.", b"<p>(<i>a</i>), <strong>b</strong>.</p>\n"),
+        (b"~~a~b~~ ++c+d++", b"<p><del>a~b</del> <u>c+d</u></p>\n"),
+    ];
+    for (input, expected_output) in cases {
+        transcribe(input, &mut output)?;
+        let o: &[u8] = output.as_ref();
+        assert_eq!(&expected_output, &o, "{}", String::from_utf8_lossy(input));
+        output.clear();
+    }
+    Ok(())
+}
+
+// every string of up to 7 `_`, `*`, `a` and ` ` transcribes to well-formed html
+#[test]
+fn test_emphasis_matrix() -> SamupResult {
+    const ALPHABET: &[u8] = b"_*a ";
+    let mut output = Vec::new();
+    for len in 1..=7 {
+        for n in 0..ALPHABET.len().pow(len) {
+            let input: Vec<u8> = (0..len)
+                .map(|i| ALPHABET[n / ALPHABET.len().pow(i) % ALPHABET.len()])
+                .collect();
+            transcribe(&input, &mut output)?;
+            let html = String::from_utf8_lossy(&output);
+            let mut open = Vec::new();
+            for tag in html.split('<').skip(1) {
+                let name = tag[..tag.find('>').unwrap()].split(' ').next().unwrap();
+                match name.strip_prefix('/') {
+                    _ if name == "hr" => {}
+                    Some(name) => assert_eq!(
+                        open.pop(),
+                        Some(name),
+                        "{:?} -> {html:?}",
+                        String::from_utf8_lossy(&input)
+                    ),
+                    None => open.push(name),
+                }
+            }
+            assert!(open.is_empty(), "{html:?}");
+            output.clear();
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_inline_styles() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"~~gone~~ ++under++ H~2~O x^2^";
+    let expected_output = b"<p><del>gone</del> <u>under</u> H<sub>2</sub>O x<sup>2</sup></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "inline styles");
+    output.clear();
+    let input = b"1 ~ 2 ^ 3 + 4 ~a b~ c++";
+    let expected_output = b"<p>1 ~ 2 ^ 3 + 4 ~a b~ c++</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "literal inline styles");
+    output.clear();
+    let input = b"H~2~O~2~ ~a~~b~ x^2^y^3 ^a^b^c";
+    let expected_output =
+        b"<p>H<sub>2</sub>O<sub>2</sub> <sub>a~~b</sub> x<sup>2</sup>y^3 <sup>a</sup>b^c</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "inline styles within a word");
+    output.clear();
+    let input = b"note[^1] ^not^";
+    let expected_output =
+        b"<p>note<a id=\"link-1\" target=\"#ref-1\"><sup>1</sup></a> <sup>not</sup></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "sup and foot note");
+    Ok(())
+}
+
+#[test]
+fn test_autolink() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"see https://swizzard.pizza. or (mailto:me@swizzard.pizza)!";
+    let expected_output = b"<p>see <a href=\"https://swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\">https://swizzard.pizza</a>. or (<a href=\"mailto:me@swizzard.pizza\" target=\"_blank\" rel=\"noopener noreferrer\">mailto:me@swizzard.pizza</a>)!</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "autolink");
+    output.clear();
+    let input = b"https://en.wikipedia.org/wiki/Rust_(language), http:// xhttps://a.b";
+    let expected_output = b"<p><a href=\"https://en.wikipedia.org/wiki/Rust_(language)\" target=\"_blank\" rel=\"noopener noreferrer\">https://en.wikipedia.org/wiki/Rust_(language)</a>, http:// xhttps://a.b</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "autolink parens");
+    output.clear();
+    let input = b"((https://a.b/(c)])).";
+    let expected_output = b"<p>((<a href=\"https://a.b/(c)\" target=\"_blank\" rel=\"noopener noreferrer\">https://a.b/(c)</a>])).</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "autolink unbalanced parens");
+    output.clear();
+    let input = b"<https://a.com> \"https://a.com\" x,https://a.com 1https://a.com";
+    let expected_output = b"<p>&lt;<a href=\"https://a.com\" target=\"_blank\" rel=\"noopener noreferrer\">https://a.com</a>&gt; \"<a href=\"https://a.com\" target=\"_blank\" rel=\"noopener noreferrer\">https://a.com</a>\" x,<a href=\"https://a.com\" target=\"_blank\" rel=\"noopener noreferrer\">https://a.com</a> 1https://a.com</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "autolink after punctuation");
+    output.clear();
+    let input = b"https://a.b ftp://c.d";
+    let expected_output =
+        b"<p>https://a.b <a href=\"ftp://c.d\" target=\"_blank\" rel=\"noopener noreferrer\">ftp://c.d</a></p>\n";
+    transcribe_with(
+        input,
+        &mut output,
+        &Options::new()
+            .autolink_schemes(["ftp://"])
+            .link_schemes(["ftp"]),
+    )?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "autolink schemes");
+    output.clear();
+    let input = b"https://a.b";
+    let expected_output = b"<p>https://a.b</p>\n";
+    transcribe_with(input, &mut output, &Options::new().autolink(false))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "no autolink");
+    Ok(())
+}
+
+#[test]
+fn test_ref_link() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"see [the _docs_][Docs] or [it][]\n\n[docs]: https://docs.rs/samup\n[it]: https://it.example\n";
+    let expected_output = b"<p>see <a href=\"https://docs.rs/samup\" target=\"_blank\" rel=\"noopener noreferrer\">the <i>docs</i></a> or <a href=\"https://it.example\" target=\"_blank\" rel=\"noopener noreferrer\">it</a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "ref link");
+    output.clear();
+    let input = b"[http://x.com][r] [see https://y.z _now_][r]\n\n[r]: https://r.example";
+    let expected_output = b"<p><a href=\"https://r.example\" target=\"_blank\" rel=\"noopener noreferrer\">http://x.com</a> <a href=\"https://r.example\" target=\"_blank\" rel=\"noopener noreferrer\">see https://y.z <i>now</i></a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "bare urls in a ref link label");
+    output.clear();
+    let input = b"# [a][b]\n\n[c][d]\n[b]: https://b.example";
+    let expected_output =
+        b"<h1 id=\"a\"><a href=\"https://b.example\" target=\"_blank\" rel=\"noopener noreferrer\">a</a></h1>\n<p>[c][d]</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "undefined ref link");
+    let expected_diagnostics = vec![Diagnostic {
+        line: 3,
+        message: String::from("undefined link reference [d]"),
+    }];
+    assert_eq!(
+        expected_diagnostics,
+        diagnostics_with(input, &Options::new())?,
+        "undefined ref link diagnostics"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_link_attributes() -> SamupResult {
+    let mut output = Vec::new();
+    let input =
+        b"[https://a.b]{title=\"a <b>\" target=_self} [https://c.d](c){rel=nofollow download}";
+    let expected_output = b"<p><a href=\"https://a.b\" target=\"_self\" title=\"a &lt;b&gt;\">https://a.b</a> <a href=\"https://c.d\" target=\"_blank\" rel=\"noopener noreferrer nofollow\" download>c</a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "link attributes");
+    output.clear();
+    let input = b"[e][f]{class=ext} [https://g.h]{onclick=x}\n\n[f]: https://f.g";
+    let expected_output = b"<p><a href=\"https://f.g\" target=\"_blank\" rel=\"noopener noreferrer\" class=\"ext\">e</a> <a href=\"https://g.h\" target=\"_blank\" rel=\"noopener noreferrer\">https://g.h</a>{onclick=x}</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "bad link attributes");
+    output.clear();
+    let input = b"[https://a.b](x){rel=} [https://c.d](y){rel=\"NoOpener me\" target=_self} [https://e.f]{id=ref-1}";
+    let expected_output = b"<p><a href=\"https://a.b\" target=\"_blank\" rel=\"noopener noreferrer\">x</a> <a href=\"https://c.d\" target=\"_self\" rel=\"NoOpener me\">y</a> <a href=\"https://e.f\" target=\"_blank\" rel=\"noopener noreferrer\">https://e.f</a>{id=ref-1}</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "link rel and id");
+    output.clear();
+    let input = b"[x](y){target=_BLANK} [z](w){target=\" _Blank \" rel=nofollow}";
+    let expected_output = b"<p><a href=\"x\" target=\"_BLANK\" rel=\"noopener noreferrer\">y</a> <a href=\"z\" target=\"_Blank\" rel=\"noopener noreferrer nofollow\">w</a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "mixed case _blank link");
+    Ok(())
+}
+
+#[test]
+fn test_disallowed_link() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"[javascript:alert(1)] [JavaScript:x](_click_) [a][r]\n[r]: data:text/html,hi";
+    let expected_output = b"<p>javascript:alert(1) <i>click</i> a</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "disallowed link");
+    let expected_diagnostics: Vec<String> = vec![
+        "line 1: disallowed link url javascript:alert(1)".into(),
+        "line 1: disallowed link url JavaScript:x".into(),
+        "line 1: disallowed link url data:text/html,hi".into(),
+    ];
+    let diagnostics: Vec<String> = diagnostics_with(input, &Options::new())?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        expected_diagnostics, diagnostics,
+        "disallowed link diagnostics"
+    );
+    output.clear();
+    let input = b"![javascript:alert(1)](a <b>){width=3} c";
+    let expected_output = b"<p>a &lt;b&gt; c</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "disallowed image");
+    let diagnostics = diagnostics_with(input, &Options::new())?;
+    assert_eq!(
+        "line 1: disallowed link url javascript:alert(1)",
+        diagnostics[0].to_string(),
+        "disallowed image diagnostic"
+    );
+    output.clear();
+    let options = Options::minified().disallowed_links(DisallowedLinks::Drop);
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&b"<p> c</p>", &o, "dropped image");
+    output.clear();
+    let input = b"a [javascript:x](b){title=c} d [ftp://e] [f]";
+    let expected_output =
+        b"<p>a  d  <a href=\"f\" target=\"_blank\" rel=\"noopener noreferrer\">f</a></p>\n";
+    let options = Options::new().disallowed_links(DisallowedLinks::Drop);
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "dropped link");
+    output.clear();
+    let input = b"[ftp://e] [https://f\"onclick=\"x]";
+    let expected_output = b"<p><a href=\"ftp://e\" target=\"_blank\" rel=\"noopener noreferrer\">ftp://e</a> https://f\"onclick=\"x</p>\n";
+    let options = Options::new().link_schemes(["ftp"]);
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "link schemes");
+    Ok(())
+}
+
+#[test]
+fn test_rewrite_links() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"[other.samup](Other page) [/a/b.samup#c] [https://d.e/f.samup]";
+    let expected_output = b"<p><a href=\"https://ex.com/docs/other.html\" target=\"_blank\" rel=\"noopener noreferrer\">Other page</a> <a href=\"https://ex.com/a/b.html#c\" target=\"_blank\" rel=\"noopener noreferrer\">/a/b.samup#c</a> <a href=\"https://d.e/f.samup\" target=\"_blank\" rel=\"noopener noreferrer\">https://d.e/f.samup</a></p>\n";
+    let options = Options::new().samup_links("https://ex.com/docs/");
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "samup links");
+    output.clear();
+    let input = b"![img.png](pic)";
+    let expected_output = b"<p><img src=\"https://ex.com/docs/img.png\" alt=\"pic\"></p>\n";
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "samup links image");
+    output.clear();
+    let input = b"[a.samup](a) [/b.samup](b)";
+    for (base_url, a, b) in [
+        (
+            "https://ex.com/docs",
+            "https://ex.com/docs/a.html",
+            "https://ex.com/b.html",
+        ),
+        ("/docs", "/docs/a.html", "/b.html"),
+        ("docs", "docs/a.html", "/b.html"),
+        (
+            "https://ex.com",
+            "https://ex.com/a.html",
+            "https://ex.com/b.html",
+        ),
+    ] {
+        let expected_output = format!(
+            "<p><a href=\"{a}\" target=\"_blank\" rel=\"noopener noreferrer\">a</a> <a href=\"{b}\" target=\"_blank\" rel=\"noopener noreferrer\">b</a></p>\n"
+        );
+        transcribe_with(input, &mut output, &Options::new().samup_links(base_url))?;
+        let o: &[u8] = output.as_ref();
+        assert_eq!(
+            expected_output.as_bytes(),
+            o,
+            "samup links under {base_url}"
+        );
+        output.clear();
+    }
+    let input = b"[a](b) https://c.d";
+    let expected_output =
+        b"<p><a href=\"A\" target=\"_blank\" rel=\"noopener noreferrer\">b</a> https://c.d</p>\n";
+    let options = Options::new().rewrite_links(|url| match url {
+        "a" => String::from("A"),
+        _ => String::from("javascript:alert(1)"),
+    });
+    transcribe_with(input, &mut output, &options)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "rewrite links");
+    Ok(())
+}
+
+#[test]
+fn test_front_matter() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"---\ntitle: \"Hello: world\"\n\ntags: a, b\n---\n# Hello\n";
+    let expected_output = b"<h1 id=\"hello\">Hello</h1>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "front matter");
+    let front_matter = metadata(input);
+    assert_eq!(
+        Some("Hello: world"),
+        front_matter.get("title"),
+        "front matter title"
+    );
+    assert_eq!(
+        vec!["a", "b"],
+        front_matter.list("tags"),
+        "front matter tags"
+    );
+    assert_eq!(None, front_matter.get("author"), "front matter author");
+    output.clear();
+    let input = b"---\nnot front matter\n---\n";
+    let expected_output = b"<hr>\n<p>not front matter</p>\n<hr>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "not front matter");
+    assert!(metadata(input).is_empty(), "no front matter");
+    Ok(())
+}
+
+#[test]
+fn test_comment() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"a\n%% note\nb\n\n%%%\nblock -- note\n%%%\n100%% sure";
+    let expected_output = b"<p>a\nb</p>\n<p>100%% sure</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "comment");
+    output.clear();
+    let expected_output =
+        b"<p>a\n<!-- note -->\nb</p>\n<!-- block - - note -->\n<p>100%% sure</p>\n";
+    transcribe_with(input, &mut output, &Options::new().keep_comments(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "kept comment");
+    output.clear();
+    let input = b"> %%%\n> secret\n> %%%\n> quoted\n\nafter";
+    let expected_output = b"<blockquote><p>quoted</p></blockquote><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "quoted comment");
+    output.clear();
+    let input = b"> %%%\n> secret\nafter";
+    let expected_output = b"<blockquote></blockquote><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "unclosed quoted comment");
+    Ok(())
+}
+
+#[test]
+fn test_escaping() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"# a < b\n<script>alert(1)</script> & ![x\"](y\")";
+    let expected_output = b"<h1 id=\"a-b\">a &lt; b</h1>\n<p>&lt;script&gt;alert(1)&lt;/script&gt; &amp; <img src=\"x&quot;\" alt=\"y&quot;\"></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "escaping");
+    Ok(())
+}
+
+#[test]
+fn test_raw_html() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"a\n~~~html\n<iframe src=\"x\"></iframe>\n~~~\nb";
+    let expected_output = b"<p>a</p>\n<iframe src=\"x\"></iframe>\n<p>b</p>\n";
+    transcribe_with(input, &mut output, &Options::new().raw_html(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "raw html");
+    output.clear();
+    let expected_output = b"<p>a</p>\n<p>&lt;iframe src=\"x\"&gt;&lt;/iframe&gt;</p>\n<p>b</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "raw html disabled");
+    output.clear();
+    transcribe_with(input, &mut output, &Options::untrusted().raw_html(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "raw html safe");
+    output.clear();
+    let input = b"> ~~~html\n> <b>x</b>\n> ~~~\n\nafter";
+    let expected_output = b"<blockquote><b>x</b></blockquote><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified().raw_html(true))?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "quoted raw html");
+    output.clear();
+    let expected_output = b"<blockquote><p>&lt;b&gt;x&lt;/b&gt;</p></blockquote><p>after</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "quoted raw html disabled");
     Ok(())
 }
 
@@ -92,16 +1038,38 @@ fn test_link_label() -> SamupResult {
 fn test_foot_note_link() -> SamupResult {
     let mut output = Vec::new();
     let input = b"note[^1]";
-    let expected_output = b"<p>note<a id=\"link-1\" target=\"#ref-1\"><sup>1</sup></a></p>";
+    let expected_output = b"<p>note<a id=\"link-1\" target=\"#ref-1\"><sup>1</sup></a></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "foot note link");
     output.clear();
     let input = b"note[^12]";
-    let expected_output = b"<p>note<a id=\"link-12\" target=\"#ref-12\"><sup>12</sup></a></p>";
+    let expected_output = b"<p>note<a id=\"link-12\" target=\"#ref-12\"><sup>12</sup></a></p>\n";
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "foot note link");
+    output.clear();
+    let input = b"note[^300] [^99999999999]";
+    let expected_output =
+        b"<p>note<a id=\"link-300\" target=\"#ref-300\"><sup>300</sup></a> [^99999999999]</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "big foot note links");
+    let diagnostics = diagnostics_with(input, &Options::default())?;
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic {
+            line: 1,
+            message: String::from("footnote number too large")
+        }],
+        "big foot note link diagnostics"
+    );
+    output.clear();
+    let input = b"[u](a [^1] b) [v][r]\n[r]: w";
+    let expected_output = b"<p><a href=\"u\" target=\"_blank\" rel=\"noopener noreferrer\">a <sup>1</sup> b</a> <a href=\"w\" target=\"_blank\" rel=\"noopener noreferrer\">v</a></p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "foot note link in a link label");
     Ok(())
 }
 
@@ -109,9 +1077,49 @@ fn test_foot_note_link() -> SamupResult {
 fn test_foot_note_ref() -> SamupResult {
     let mut output = Vec::new();
     let input = b"[^1]: foo";
-    let expected_output: &[u8] = "<p class=\"footnote\" id=\"ref-1\"><span class=\"footnote\">1:</span> foo<a href=\"#link-1\">\u{1f519}</a></p>".as_ref();
+    let expected_output: &[u8] = "<p class=\"footnote\" id=\"ref-1\"><span class=\"footnote\">1:</span> foo<a href=\"#link-1\">\u{1f519}</a></p>\n".as_ref();
     transcribe(input, &mut output)?;
     let o: &[u8] = output.as_ref();
     assert_eq!(&expected_output, &o, "foot note ref");
+    output.clear();
+    let input = b"[^300]: foo\n[^99999999999]: bar";
+    let expected_output: &[u8] = "<p class=\"footnote\" id=\"ref-300\"><span class=\"footnote\">300:</span> foo<a href=\"#link-300\">\u{1f519}</a></p><p>[^99999999999]: bar</p>".as_ref();
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "big foot note refs");
+    Ok(())
+}
+
+#[test]
+fn test_pretty() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"# h1\nabc\ndef\n\n\n[^1]: foo\n## h2";
+    let expected_output: &[u8] = "<h1 id=\"h1\">h1</h1>\n<p>abc\ndef</p>\n<p class=\"footnote\" id=\"ref-1\"><span class=\"footnote\">1:</span> foo<a href=\"#link-1\">\u{1f519}</a></p>\n<h2 id=\"h2\">h2</h2>\n".as_ref();
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "pretty");
+    output.clear();
+    let input = b"  abc\r\n  \r\n  def";
+    let expected_output = b"<p>abc</p>\n<p>def</p>\n";
+    transcribe(input, &mut output)?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "pretty whitespace");
     Ok(())
 }
+
+#[test]
+fn test_minified() -> SamupResult {
+    let mut output = Vec::new();
+    let input = b"# h1\n_abc_\ndef\n\nghi";
+    let expected_output = b"<h1 id=\"h1\">h1</h1><p><i>abc</i>\ndef</p><p>ghi</p>";
+    transcribe_with(input, &mut output, &Options::minified())?;
+    let o: &[u8] = output.as_ref();
+    assert_eq!(&expected_output, &o, "minified");
+    Ok(())
+}
+
+#[test]
+fn test_transcriber_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Transcriber>();
+}